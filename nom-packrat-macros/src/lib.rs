@@ -4,80 +4,268 @@ extern crate proc_macro;
 
 use crate::proc_macro::TokenStream;
 use quote::ToTokens;
-use syn::{self, parse_macro_input, parse_quote, AttributeArgs, FnArg, ItemFn, Stmt};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{self, parse_macro_input, parse_quote, Expr, FnArg, ItemFn, Pat, Path, Stmt, Token};
 
 /// Custom attribute for packrat parser
+///
+/// # Arguments
+/// * (Optional) `left_recursive`: Allow the parser to call itself at the same position.
+///   The result is grown from a failed seed until it stops getting longer.
+///   If another parser in the recursion is called first, its re-entry fails like the seed.
+/// * (Optional) `context`: Use the storage carried by the input through `PackratContext`
+///   instead of the storage declared by `storage!`.
+/// * (Optional) `storage = NAME`: Use the storage declared by `storage!(name = NAME, ..)`.
+///   The path is resolved as written, so a storage in other module is used by a path like `my_grammar::STORAGE`.
+/// * (Optional) `shared`: Share the results across parses by the content of the remaining input instead of its position.
+///   The shared results are kept by `init!()` and the end of session, so identical inputs like repeated snippets are not parsed again.
+///   The remaining input is hashed at each cache miss, so it should be used for parsers called with short inputs,
+///   and the remaining input longer than 1024 bytes is not shared.
+///   The input type must implement `nom::AsBytes`, and the parser must depend only on the input and the extra state.
 #[proc_macro_attribute]
 pub fn packrat_parser(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let attr = parse_macro_input!(attr as AttributeArgs);
+    let attr = match Punctuated::<Expr, Token![,]>::parse_terminated.parse(attr) {
+        Ok(x) => x,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let item = parse_macro_input!(item as ItemFn);
     impl_packrat_parser(&attr, &item)
 }
 
-fn impl_packrat_parser(_attr: &AttributeArgs, item: &ItemFn) -> TokenStream {
-    let before = impl_packrat_parser_bofore(&item);
-    let body = impl_packrat_parser_body(&item);
-    let after = impl_packrat_parser_after(&item);
+struct PackratParserArgs {
+    left_recursive: bool,
+    context: bool,
+    shared: bool,
+    storage: Path,
+}
+
+fn parse_args(attr: &Punctuated<Expr, Token![,]>) -> PackratParserArgs {
+    let mut args = PackratParserArgs {
+        left_recursive: false,
+        context: false,
+        shared: false,
+        storage: parse_quote!(crate::PACKRAT_STORAGE),
+    };
+
+    for arg in attr {
+        match arg {
+            Expr::Path(x) if x.path.is_ident("left_recursive") => {
+                args.left_recursive = true;
+            }
+            Expr::Path(x) if x.path.is_ident("context") => {
+                args.context = true;
+            }
+            Expr::Path(x) if x.path.is_ident("shared") => {
+                args.shared = true;
+            }
+            Expr::Assign(x) if matches!(&*x.left, Expr::Path(y) if y.path.is_ident("storage")) => {
+                match &*x.right {
+                    Expr::Path(y) => {
+                        args.storage = y.path.clone();
+                    }
+                    _ => panic!(
+                        "storage of #[packrat_parser] must be a path: {}",
+                        x.right.to_token_stream()
+                    ),
+                }
+            }
+            _ => panic!(
+                "unknown argument of #[packrat_parser]: {}",
+                arg.to_token_stream()
+            ),
+        }
+    }
+
+    args
+}
+
+fn impl_packrat_parser(attr: &Punctuated<Expr, Token![,]>, item: &ItemFn) -> TokenStream {
+    let args = parse_args(attr);
+
+    let rule_id = impl_packrat_parser_rule_id(item);
+    let before = impl_packrat_parser_bofore(item, &args);
+    let body = if args.left_recursive {
+        impl_packrat_parser_body_left_recursive(item, &args)
+    } else {
+        impl_packrat_parser_body(item)
+    };
+    let after = impl_packrat_parser_after(item, &args);
 
     let mut item = item.clone();
 
     item.block.stmts.clear();
+    item.block.stmts.extend(rule_id);
     item.block.stmts.push(before);
     item.block.stmts.push(body);
     item.block.stmts.push(after);
 
+    // `feature = "trace"` is a feature of the crate using nom-packrat
+    item.attrs.push(parse_quote!(#[allow(unexpected_cfgs)]));
+
     item.into_token_stream().into()
 }
 
-fn impl_packrat_parser_bofore(item: &ItemFn) -> Stmt {
-    let ident = &item.sig.ident;
+fn get_input(item: &ItemFn) -> &Pat {
+    if let Some(FnArg::Typed(arg)) = &item.sig.inputs.first() {
+        &arg.pat
+    } else {
+        panic!("function with #[packrat_parser] must have an argument");
+    }
+}
 
-    let input = if let Some(x) = &item.sig.inputs.first() {
-        match x {
-            FnArg::Typed(arg) => &arg.pat,
-            _ => panic!("function with #[packrat_parser] must have an argument"),
+/// Call `body` with `storage: &RefCell<PackratStorage<..>>`
+fn impl_storage_with(args: &PackratParserArgs, input: &Expr, body: Expr) -> Expr {
+    if args.context {
+        parse_quote! {
+            {
+                let storage = nom_packrat::PackratContext::packrat_storage(&#input);
+                #body
+            }
         }
     } else {
-        panic!("function with #[packrat_parser] must have an argument");
-    };
+        let storage = &args.storage;
+        parse_quote! {
+            #storage.with(|storage| #body)
+        }
+    }
+}
 
-    parse_quote! {
-        let org_input = if let Some(x) = crate::PACKRAT_STORAGE.with(|storage| {
-            use nom::AsBytes;
-            use nom_packrat::HasExtraState;
-            let ptr = #input.as_bytes().as_ptr();
-            let extra = #input.get_extra_state();
-            if let Some(x) = storage.borrow_mut().get(&(stringify!(#ident), ptr, extra)) {
-                if let Some((x, y)) = x {
-                    return Some(Some((x.clone(), *y)))
-                } else {
-                    return Some(None)
-                }
-            } else {
-                return None
+fn impl_packrat_parser_rule_id(item: &ItemFn) -> Vec<Stmt> {
+    let ident = &item.sig.ident;
+
+    // Qualify by module path to distinguish parsers with the same name
+    vec![
+        parse_quote! {
+            const PACKRAT_RULE_NAME: &str = concat!(module_path!(), "::", stringify!(#ident));
+        },
+        parse_quote! {
+            const PACKRAT_RULE_ID: u64 = nom_packrat::rule_id(PACKRAT_RULE_NAME);
+        },
+    ]
+}
+
+fn impl_packrat_parser_bofore(item: &ItemFn, args: &PackratParserArgs) -> Stmt {
+    let ident = &item.sig.ident;
+    let input = get_input(item);
+    let input_expr: Expr = parse_quote!(#input);
+
+    // The seed of left-recursive parser is marked by `begin_growing`
+    let mark: Option<Stmt> = if args.left_recursive {
+        None
+    } else {
+        Some(parse_quote! {
+            if entry.is_none() {
+                storage.insert(PackratKey::new(PACKRAT_RULE_ID, position, extra), PackratEntry::InProgress);
             }
-        }) {
-            if let Some((x, y)) = x {
-                use nom::InputTake;
-                let (s, _) = #input.take_split(y);
-                use std::convert::TryInto;
-                let x = x.try_into().map_err(|_| nom::Err::Error(nom::error::make_error(#input, nom::error::ErrorKind::Fix)))?;
-                #[cfg(feature = "trace")]
-                {
-                    use nom_tracable::Tracable;
-                    nom_tracable::custom_trace(&#input, stringify!(#ident), "packrat cache hit (accepted)", "\u{001b}[1;33m")
-                };
-                return Ok((s, x))
-            } else {
+        })
+    };
+
+    let in_progress: Expr = if args.left_recursive {
+        parse_quote! {
+            {
                 #[cfg(feature = "trace")]
                 {
                     use nom_tracable::Tracable;
-                    nom_tracable::custom_trace(&#input, stringify!(#ident), "packrat cache hit (rejected)", "\u{001b}[1;33m")
+                    nom_tracable::custom_trace(&#input, stringify!(#ident), "packrat seed hit (rejected)", "\u{001b}[1;33m")
                 };
                 return Err(nom::Err::Error(nom::error::make_error(#input, nom::error::ErrorKind::Fix)));
             }
-        } else {
-            #input
+        }
+    } else {
+        let reject = impl_storage_with(
+            args,
+            &input_expr,
+            parse_quote! {
+                storage.borrow_mut().reject_running(&PackratKey::new(PACKRAT_RULE_ID, position, #input.get_extra_state()))
+            },
+        );
+        let offset = impl_storage_with(
+            args,
+            &input_expr,
+            parse_quote!(storage.borrow().offset(position)),
+        );
+        parse_quote! {
+            {
+                // The parser is involved in the left recursion of the seed growing at the position
+                if #reject {
+                    #[cfg(feature = "trace")]
+                    {
+                        use nom_tracable::Tracable;
+                        nom_tracable::custom_trace(&#input, stringify!(#ident), "packrat seed hit (rejected)", "\u{001b}[1;33m")
+                    };
+                    return Err(nom::Err::Error(nom::error::make_error(#input, nom::error::ErrorKind::Fix)));
+                }
+                let offset = #offset;
+                panic!(
+                    "left recursion detected: `{}` is called again at offset {} before it returns (use #[packrat_parser(left_recursive)] to allow it)",
+                    PACKRAT_RULE_NAME,
+                    offset
+                );
+            }
+        }
+    };
+
+    let lookup: Expr = if args.shared {
+        parse_quote! {
+            storage.lookup_shared(&PackratKey::new(PACKRAT_RULE_ID, position, extra.clone()), nom::AsBytes::as_bytes(&#input), PACKRAT_RULE_NAME)
+        }
+    } else {
+        parse_quote! {
+            storage.lookup(&PackratKey::new(PACKRAT_RULE_ID, position, extra.clone()), PACKRAT_RULE_NAME)
+        }
+    };
+
+    let entry = impl_storage_with(
+        args,
+        &input_expr,
+        parse_quote! {
+            {
+                let mut storage = storage.borrow_mut();
+                storage.check_input(position, nom::InputLength::input_len(&#input));
+                let entry = #lookup.cloned();
+                #mark
+                entry
+            }
+        },
+    );
+
+    parse_quote! {
+        let org_input = {
+            use nom_packrat::{HasExtraState, PackratEntry, PackratInput, PackratKey};
+            let position = #input.packrat_position();
+            let extra = #input.get_extra_state();
+            match #entry {
+                Some(PackratEntry::Accepted(x, y)) => {
+                    let s = #input.packrat_advance(y);
+                    use std::convert::TryInto;
+                    let x = x.try_into().map_err(|_| nom::Err::Error(nom::error::make_error(#input, nom::error::ErrorKind::Fix)))?;
+                    #[cfg(feature = "trace")]
+                    {
+                        use nom_tracable::Tracable;
+                        nom_tracable::custom_trace(&#input, stringify!(#ident), "packrat cache hit (accepted)", "\u{001b}[1;33m")
+                    };
+                    return Ok((s, x))
+                }
+                Some(PackratEntry::Rejected(e)) => {
+                    #[cfg(feature = "trace")]
+                    {
+                        use nom_tracable::Tracable;
+                        nom_tracable::custom_trace(&#input, stringify!(#ident), "packrat cache hit (rejected)", "\u{001b}[1;33m")
+                    };
+                    return Err(nom::Err::Error(nom_packrat::PackratError::to_error(&e, #input)));
+                }
+                Some(PackratEntry::Failure(e)) => {
+                    #[cfg(feature = "trace")]
+                    {
+                        use nom_tracable::Tracable;
+                        nom_tracable::custom_trace(&#input, stringify!(#ident), "packrat cache hit (failure)", "\u{001b}[1;33m")
+                    };
+                    return Err(nom::Err::Failure(nom_packrat::PackratError::to_error(&e, #input)));
+                }
+                Some(PackratEntry::InProgress) => #in_progress
+                None => #input,
+            }
         };
     }
 }
@@ -92,35 +280,152 @@ fn impl_packrat_parser_body(item: &ItemFn) -> Stmt {
     }
 }
 
-fn impl_packrat_parser_after(item: &ItemFn) -> Stmt {
+fn impl_packrat_parser_body_left_recursive(item: &ItemFn, args: &PackratParserArgs) -> Stmt {
     let ident = &item.sig.ident;
+    let body = item.block.as_ref();
+    let input: Expr = parse_quote!(org_input);
+
+    let begin = impl_storage_with(
+        args,
+        &input,
+        parse_quote! {
+            storage.borrow_mut().begin_growing(PackratKey::new(PACKRAT_RULE_ID, position, extra.clone()))
+        },
+    );
+    let grow = impl_storage_with(
+        args,
+        &input,
+        parse_quote! {
+            storage.borrow_mut().grow(PackratEntry::Accepted((*x).clone().into(), len))
+        },
+    );
+    let end = impl_storage_with(
+        args,
+        &input,
+        parse_quote!(storage.borrow_mut().end_growing()),
+    );
 
     parse_quote! {
-        {
-            use nom::AsBytes;
-            use nom_packrat::HasExtraState;
-            let ptr = org_input.as_bytes().as_ptr();
+        let body_ret = {
+            use nom_packrat::{HasExtraState, PackratEntry, PackratInput, PackratKey};
+            let position = org_input.packrat_position();
             let extra = org_input.get_extra_state();
-            if let Ok((s, x)) = &body_ret {
-                use nom::Offset;
-                let len = org_input.offset(&s);
-                crate::PACKRAT_STORAGE.with(|storage| {
-                    storage.borrow_mut().insert((stringify!(#ident), ptr, extra), Some(((*x).clone().into(), len)));
-                });
-                #[cfg(feature = "trace")]
-                {
-                    use nom_tracable::Tracable;
-                    nom_tracable::custom_trace(&org_input, stringify!(#ident), "packrat cache store (accepted)", "\u{001b}[1;33m");
-                };
-            } else {
-                crate::PACKRAT_STORAGE.with(|storage| {
-                    storage.borrow_mut().insert((stringify!(#ident), ptr, extra), None);
-                });
+
+            // Seed the storage by failure, and grow the result while it becomes longer
+            #begin;
+            let body = || { #body };
+            let mut body_ret = body();
+            while let Ok((s, x)) = &body_ret {
+                let len = s.packrat_position().wrapping_sub(position);
+                #grow;
                 #[cfg(feature = "trace")]
                 {
                     use nom_tracable::Tracable;
-                    nom_tracable::custom_trace(&org_input, stringify!(#ident), "packrat cache store (rejected)", "\u{001b}[1;33m");
+                    nom_tracable::custom_trace(&org_input, stringify!(#ident), "packrat seed grow", "\u{001b}[1;33m");
                 };
+                let next_ret = body();
+                if !matches!(&next_ret, Ok((s, _)) if s.packrat_position().wrapping_sub(position) > len) {
+                    break;
+                }
+                body_ret = next_ret;
+            }
+            #end;
+            body_ret
+        };
+    }
+}
+
+/// Store `entry` at the position, and share it if the parser is shared
+fn impl_packrat_parser_store(args: &PackratParserArgs, input: &Expr, entry: Expr) -> Expr {
+    let shared: Option<Stmt> = if args.shared {
+        Some(parse_quote! {
+            storage.insert_shared(&key, nom::AsBytes::as_bytes(&#input), entry.clone());
+        })
+    } else {
+        None
+    };
+
+    impl_storage_with(
+        args,
+        input,
+        parse_quote! {
+            {
+                let mut storage = storage.borrow_mut();
+                let key = PackratKey::new(PACKRAT_RULE_ID, position, extra);
+                let entry = #entry;
+                #shared
+                storage.store(key, entry, PACKRAT_RULE_NAME);
+            }
+        },
+    )
+}
+
+fn impl_packrat_parser_after(item: &ItemFn, args: &PackratParserArgs) -> Stmt {
+    let ident = &item.sig.ident;
+    let input: Expr = parse_quote!(org_input);
+
+    let accepted = impl_packrat_parser_store(
+        args,
+        &input,
+        parse_quote!(PackratEntry::Accepted((*x).clone().into(), len)),
+    );
+    let rejected = impl_packrat_parser_store(
+        args,
+        &input,
+        parse_quote!(PackratEntry::Rejected(PackratError::from_error(
+            &org_input, e
+        ))),
+    );
+    let failure = impl_packrat_parser_store(
+        args,
+        &input,
+        parse_quote!(PackratEntry::Failure(PackratError::from_error(
+            &org_input, e
+        ))),
+    );
+    let incomplete = impl_storage_with(
+        args,
+        &input,
+        parse_quote! {
+            storage.borrow_mut().remove(&PackratKey::new(PACKRAT_RULE_ID, position, extra))
+        },
+    );
+
+    parse_quote! {
+        {
+            use nom_packrat::{HasExtraState, PackratEntry, PackratError, PackratInput, PackratKey};
+            let position = org_input.packrat_position();
+            let extra = org_input.get_extra_state();
+            match &body_ret {
+                Ok((s, x)) => {
+                    let len = s.packrat_position().wrapping_sub(position);
+                    #accepted;
+                    #[cfg(feature = "trace")]
+                    {
+                        use nom_tracable::Tracable;
+                        nom_tracable::custom_trace(&org_input, stringify!(#ident), "packrat cache store (accepted)", "\u{001b}[1;33m");
+                    };
+                }
+                Err(nom::Err::Error(e)) => {
+                    #rejected;
+                    #[cfg(feature = "trace")]
+                    {
+                        use nom_tracable::Tracable;
+                        nom_tracable::custom_trace(&org_input, stringify!(#ident), "packrat cache store (rejected)", "\u{001b}[1;33m");
+                    };
+                }
+                Err(nom::Err::Failure(e)) => {
+                    #failure;
+                    #[cfg(feature = "trace")]
+                    {
+                        use nom_tracable::Tracable;
+                        nom_tracable::custom_trace(&org_input, stringify!(#ident), "packrat cache store (failure)", "\u{001b}[1;33m");
+                    };
+                }
+                // There is no error to be restored
+                Err(nom::Err::Incomplete(_)) => {
+                    #incomplete;
+                }
             }
             body_ret
         }
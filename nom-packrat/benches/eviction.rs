@@ -0,0 +1,191 @@
+#![feature(test)]
+
+extern crate test;
+
+use nom_packrat::{init, storage, FifoCache, LruCache, PackratCache, PackratStorage};
+use std::hash::Hash;
+use test::Bencher;
+
+enum Policy<K, V> {
+    Fifo(FifoCache<K, V>),
+    Lru(LruCache<K, V>),
+}
+
+// Cache backend to compare eviction policies by hit rate
+pub struct BenchCache<K, V> {
+    policy: Policy<K, V>,
+    hit: usize,
+    miss: usize,
+}
+
+impl<K, V> BenchCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn lru(size: Option<usize>) -> Self {
+        BenchCache {
+            policy: Policy::Lru(LruCache::new(size)),
+            hit: 0,
+            miss: 0,
+        }
+    }
+}
+
+impl<K, V> PackratCache<K, V> for BenchCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn new(size: Option<usize>) -> Self {
+        BenchCache {
+            policy: Policy::Fifo(FifoCache::new(size)),
+            hit: 0,
+            miss: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let ret = match &mut self.policy {
+            Policy::Fifo(x) => x.get(key),
+            Policy::Lru(x) => x.get(key),
+        };
+        if ret.is_some() {
+            self.hit += 1;
+        } else {
+            self.miss += 1;
+        }
+        ret
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        match &mut self.policy {
+            Policy::Fifo(x) => x.insert(key, value),
+            Policy::Lru(x) => x.insert(key, value),
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        match &mut self.policy {
+            Policy::Fifo(x) => x.remove(key),
+            Policy::Lru(x) => x.remove(key),
+        }
+    }
+
+    fn clear(&mut self) {
+        match &mut self.policy {
+            Policy::Fifo(x) => x.clear(),
+            Policy::Lru(x) => x.clear(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match &self.policy {
+            Policy::Fifo(x) => x.len(),
+            Policy::Lru(x) => x.len(),
+        }
+    }
+}
+
+storage!(cache = BenchCache, Vec<String>);
+
+mod packrat_parser {
+    use nom::branch::*;
+    use nom::character::complete::*;
+    use nom::IResult;
+    use nom_packrat::packrat_parser;
+
+    #[packrat_parser]
+    pub fn p1(s: &str) -> IResult<&str, Vec<String>> {
+        alt((p2, p3, p4))(s)
+    }
+
+    #[packrat_parser]
+    pub fn p2(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, mut x) = p4(s)?;
+        let (s, y) = char('+')(s)?;
+        let (s, mut z) = p1(s)?;
+        let mut ret = Vec::new();
+        ret.append(&mut x);
+        ret.append(&mut vec![y.to_string()]);
+        ret.append(&mut z);
+        Ok((s, ret))
+    }
+
+    #[packrat_parser]
+    pub fn p3(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, mut x) = p4(s)?;
+        let (s, y) = char('-')(s)?;
+        let (s, mut z) = p1(s)?;
+        let mut ret = Vec::new();
+        ret.append(&mut x);
+        ret.append(&mut vec![y.to_string()]);
+        ret.append(&mut z);
+        Ok((s, ret))
+    }
+
+    #[packrat_parser]
+    pub fn p4(s: &str) -> IResult<&str, Vec<String>> {
+        alt((p5, p6))(s)
+    }
+
+    #[packrat_parser]
+    pub fn p5(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, x) = char('(')(s)?;
+        let (s, mut y) = p1(s)?;
+        let (s, z) = char(')')(s)?;
+        let mut ret = Vec::new();
+        ret.append(&mut vec![x.to_string()]);
+        ret.append(&mut y);
+        ret.append(&mut vec![z.to_string()]);
+        Ok((s, ret))
+    }
+
+    #[packrat_parser]
+    pub fn p6(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, x) = char('a')(s)?;
+        Ok((s, vec![x.to_string()]))
+    }
+}
+
+static STR_7PAIR: &str = "(((((((a)))))))";
+
+// Hit rates are printed by `cargo bench --bench eviction -- --nocapture`
+const EVICTION_SIZE: usize = 8;
+
+fn bench_eviction(b: &mut Bencher, lru: bool, input: &str) {
+    PACKRAT_STORAGE.with(|storage| {
+        let size = Some(EVICTION_SIZE);
+        let cache = if lru {
+            BenchCache::lru(size)
+        } else {
+            BenchCache::new(size)
+        };
+        *storage.borrow_mut() = PackratStorage::with_cache(cache);
+    });
+
+    b.iter(|| {
+        init!();
+        packrat_parser::p1(input)
+    });
+
+    PACKRAT_STORAGE.with(|storage| {
+        let storage = storage.replace(PackratStorage::new(None));
+        let cache = storage.cache();
+        println!(
+            "{} hit rate: {:.1}% ({} / {})",
+            if lru { "lru " } else { "fifo" },
+            cache.hit as f64 * 100.0 / (cache.hit + cache.miss) as f64,
+            cache.hit,
+            cache.hit + cache.miss
+        );
+    });
+}
+
+#[bench]
+fn bench_7pair_eviction_fifo(b: &mut Bencher) {
+    bench_eviction(b, false, STR_7PAIR);
+}
+
+#[bench]
+fn bench_7pair_eviction_lru(b: &mut Bencher) {
+    bench_eviction(b, true, STR_7PAIR);
+}
@@ -187,34 +187,34 @@ static STR_PAIRS: [&str; 8] = [
 ];
 
 fn main() {
-    for i in 0..8 {
-        let reg = Region::new(&GLOBAL);
-        let x = non_packrat_parser::p1(STR_PAIRS[i]);
+    for (i, pair) in STR_PAIRS.iter().enumerate() {
+        let reg = Region::new(GLOBAL);
+        let x = non_packrat_parser::p1(pair);
         println!(
             "{}pair: original   : {:<4} bytes",
             i,
             reg.change().bytes_allocated - reg.change().bytes_deallocated
         );
-        std::mem::size_of_val(&x);
+        let _ = std::mem::size_of_val(&x);
 
         init!();
-        let reg = Region::new(&GLOBAL);
-        let x = packrat_parser::p1(STR_PAIRS[i]);
+        let reg = Region::new(GLOBAL);
+        let x = packrat_parser::p1(pair);
         println!(
             "{}pair: packrat    : {:<4} bytes",
             i,
             reg.change().bytes_allocated - reg.change().bytes_deallocated
         );
-        std::mem::size_of_val(&x);
+        let _ = std::mem::size_of_val(&x);
 
         init!();
-        let reg = Region::new(&GLOBAL);
-        let x = packrat_parser_opt::p1(STR_PAIRS[i]);
+        let reg = Region::new(GLOBAL);
+        let x = packrat_parser_opt::p1(pair);
         println!(
             "{}pair: packrat_opt: {:<4} bytes",
             i,
             reg.change().bytes_allocated - reg.change().bytes_deallocated
         );
-        std::mem::size_of_val(&x);
+        let _ = std::mem::size_of_val(&x);
     }
 }
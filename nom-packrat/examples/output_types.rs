@@ -11,8 +11,10 @@ pub enum Output {
 }
 
 // Declare types for parser output
+#[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub struct A(String);
+#[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub struct AB((A, String));
 
@@ -0,0 +1,216 @@
+use nom::branch::alt;
+use nom::character::complete::{char, digit1};
+use nom::sequence::tuple;
+use nom::{
+    Compare, CompareResult, IResult, InputIter, InputLength, InputTake, Needed, Slice,
+    UnspecializedInput,
+};
+use nom_packrat::{init, packrat_parser, storage, HasExtraState, PackratInput};
+use std::fmt;
+use std::ops::RangeFrom;
+
+storage!(String);
+
+// Declare text storage which is not contiguous
+#[derive(Debug)]
+pub struct Rope {
+    chunks: Vec<String>,
+}
+
+// Declare input type pointing a range of rope by global byte offset
+#[derive(Clone, Copy, Debug)]
+pub struct Input<'a> {
+    rope: &'a Rope,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Input<'a> {
+    fn new(rope: &'a Rope) -> Self {
+        let end = rope.chunks.iter().map(|x| x.len()).sum();
+        Input {
+            rope,
+            start: 0,
+            end,
+        }
+    }
+
+    // Bytes with the offset from `start`
+    fn bytes(&self) -> impl Iterator<Item = (usize, u8)> + 'a {
+        let (start, end) = (self.start, self.end);
+        self.rope
+            .chunks
+            .iter()
+            .scan(0, |base, x| {
+                let chunk = (*base, x);
+                *base += x.len();
+                Some(chunk)
+            })
+            .filter(move |(base, x)| base + x.len() > start && *base < end)
+            .flat_map(|(base, x)| x.bytes().enumerate().map(move |(i, c)| (base + i, c)))
+            .skip_while(move |(i, _)| *i < start)
+            .take_while(move |(i, _)| *i < end)
+            .map(move |(i, c)| (i - start, c))
+    }
+
+    // Chars with the offset from `start`
+    fn chars(&self) -> impl Iterator<Item = (usize, char)> + 'a {
+        let mut buf = Vec::new();
+        self.bytes().filter_map(move |(i, c)| {
+            buf.push(c);
+            let x = std::str::from_utf8(&buf).ok()?.chars().next()?;
+            let pos = i + 1 - buf.len();
+            buf.clear();
+            Some((pos, x))
+        })
+    }
+}
+
+impl<'a> fmt::Display for Input<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (_, c) in self.chars() {
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+// Implement nom's input traits
+impl<'a> InputLength for Input<'a> {
+    fn input_len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+impl<'a> InputTake for Input<'a> {
+    fn take(&self, count: usize) -> Self {
+        Input {
+            end: self.start + count,
+            ..*self
+        }
+    }
+
+    fn take_split(&self, count: usize) -> (Self, Self) {
+        (self.slice(count..), self.take(count))
+    }
+}
+
+impl<'a> Slice<RangeFrom<usize>> for Input<'a> {
+    fn slice(&self, range: RangeFrom<usize>) -> Self {
+        Input {
+            start: self.start + range.start,
+            ..*self
+        }
+    }
+}
+
+impl<'a> InputIter for Input<'a> {
+    type Item = char;
+    type Iter = Box<dyn Iterator<Item = (usize, char)> + 'a>;
+    type IterElem = Box<dyn Iterator<Item = char> + 'a>;
+
+    fn iter_indices(&self) -> Self::Iter {
+        Box::new(self.chars())
+    }
+
+    fn iter_elements(&self) -> Self::IterElem {
+        Box::new(self.chars().map(|(_, c)| c))
+    }
+
+    fn position<P>(&self, predicate: P) -> Option<usize>
+    where
+        P: Fn(Self::Item) -> bool,
+    {
+        self.chars().find(|(_, c)| predicate(*c)).map(|(i, _)| i)
+    }
+
+    fn slice_index(&self, count: usize) -> Result<usize, Needed> {
+        let mut cnt = 0;
+        for (i, _) in self.chars() {
+            if cnt == count {
+                return Ok(i);
+            }
+            cnt += 1;
+        }
+        if cnt == count {
+            Ok(self.input_len())
+        } else {
+            Err(Needed::new(count - cnt))
+        }
+    }
+}
+
+// `InputTakeAtPosition` is derived from the traits above
+impl<'a> UnspecializedInput for Input<'a> {}
+
+impl<'a, 'b> Compare<&'b str> for Input<'a> {
+    fn compare(&self, t: &'b str) -> CompareResult {
+        let mut bytes = self.bytes();
+        for x in t.bytes() {
+            match bytes.next() {
+                Some((_, c)) if c == x => (),
+                Some(_) => return CompareResult::Error,
+                None => return CompareResult::Incomplete,
+            }
+        }
+        CompareResult::Ok
+    }
+
+    fn compare_no_case(&self, t: &'b str) -> CompareResult {
+        let mut bytes = self.bytes();
+        for x in t.bytes() {
+            match bytes.next() {
+                Some((_, c)) if c.eq_ignore_ascii_case(&x) => (),
+                Some(_) => return CompareResult::Error,
+                None => return CompareResult::Incomplete,
+            }
+        }
+        CompareResult::Ok
+    }
+}
+
+// Implement packrat's input traits
+// The position is the global byte offset, so it doesn't depend on the chunk layout
+impl<'a> PackratInput for Input<'a> {
+    fn packrat_position(&self) -> usize {
+        self.start
+    }
+
+    fn packrat_advance(&self, len: usize) -> Self {
+        self.slice(len..)
+    }
+}
+
+impl<'a> HasExtraState<()> for Input<'a> {
+    fn get_extra_state(&self) {}
+}
+
+#[packrat_parser]
+pub fn num(s: Input) -> IResult<Input, String> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[packrat_parser]
+pub fn expr(s: Input) -> IResult<Input, String> {
+    alt((add, num))(s)
+}
+
+#[packrat_parser]
+pub fn add(s: Input) -> IResult<Input, String> {
+    let (s, (x, _, y)) = tuple((num, char('+'), expr))(s)?;
+    Ok((s, format!("({}+{})", x, y)))
+}
+
+fn main() {
+    let rope = Rope {
+        chunks: vec![String::from("1+2"), String::from("3+4"), String::from("56")],
+    };
+
+    init!();
+    let (rest, x) = expr(Input::new(&rope)).unwrap();
+
+    assert_eq!(0, rest.input_len());
+    assert_eq!("(1+(23+456))", x);
+    println!("{}", x);
+}
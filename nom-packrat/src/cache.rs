@@ -0,0 +1,392 @@
+use crate::{PackratBuildHasher, PackratKey};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::ops::Range;
+
+/// Backend of packrat storage
+///
+/// `K` is a key type and `V` is an entry type of packrat storage.
+pub trait PackratCache<K, V> {
+    /// Create the cache which keeps `size` entries at most
+    fn new(size: Option<usize>) -> Self;
+
+    fn get(&mut self, key: &K) -> Option<&V>;
+
+    /// Insert the entry, and return the entry replaced by it or evicted by the size limit
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)>;
+
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    fn clear(&mut self);
+
+    /// Remove all entries, and return them
+    ///
+    /// The default implementation drops the entries, so they can't be reused by `PackratStorage::apply_edit`.
+    fn drain(&mut self) -> Vec<(K, V)> {
+        self.clear();
+        Vec::new()
+    }
+
+    /// Return all entries without removing them
+    ///
+    /// The default implementation returns nothing, so the entries can't be exported by `PackratStorage::save`.
+    fn entries(&self) -> Vec<(&K, &V)> {
+        Vec::new()
+    }
+
+    /// Release the memory which is not used by the current entries
+    fn shrink_to_fit(&mut self) {}
+
+    /// Notify the range of positions of the input checked by `PackratStorage::check_input`
+    ///
+    /// It is notified again when the range is extended or the input is changed.
+    fn input_range(&mut self, _range: Range<usize>) {}
+
+    /// Whether `PackratStorage::clear` can keep the entries until they are overwritten
+    ///
+    /// If this is `true`, the cleared entries are treated as absent by the generation of storage,
+    /// and `clear` of the cache is called only when they become too many.
+    /// The default is `false`, so `clear` of the cache is called at each `PackratStorage::clear`.
+    fn lazy_clear(&self) -> bool {
+        false
+    }
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Cache evicting the oldest inserted entry
+pub struct FifoCache<K, V> {
+    size: Option<usize>,
+    map: HashMap<K, (V, u64), PackratBuildHasher>,
+    keys: VecDeque<(K, u64)>,
+    stamp: u64,
+}
+
+impl<K, V> PackratCache<K, V> for FifoCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn new(size: Option<usize>) -> Self {
+        let init_size = size.unwrap_or(0);
+        FifoCache {
+            size,
+            map: HashMap::with_capacity_and_hasher(init_size, Default::default()),
+            keys: VecDeque::with_capacity(init_size),
+            stamp: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.map.get(key).map(|x| &x.0)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some((x, _)) = self.map.get_mut(&key) {
+            let value = std::mem::replace(x, value);
+            return Some((key, value));
+        }
+
+        let mut evicted = None;
+        if let Some(size) = self.size {
+            if self.map.len() > size - 1 {
+                while let Some((key, stamp)) = self.keys.pop_front() {
+                    if self.map.get(&key).is_some_and(|x| x.1 == stamp) {
+                        evicted = self.map.remove_entry(&key).map(|(k, v)| (k, v.0));
+                        break;
+                    }
+                }
+            }
+            self.stamp += 1;
+            self.keys.push_back((key.clone(), self.stamp));
+        }
+        self.map.insert(key, (value, self.stamp));
+        evicted
+    }
+
+    // The key is left in the queue, and skipped at eviction
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let ret = self.map.remove(key).map(|x| x.0);
+
+        // Drop keys removed after they were pushed
+        if self.keys.len() > self.map.len() * 2 {
+            let map = &self.map;
+            self.keys
+                .retain(|(key, stamp)| map.get(key).is_some_and(|x| x.1 == *stamp));
+        }
+        ret
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.keys.clear();
+    }
+
+    fn drain(&mut self) -> Vec<(K, V)> {
+        self.keys.clear();
+        self.map.drain().map(|(k, v)| (k, v.0)).collect()
+    }
+
+    fn entries(&self) -> Vec<(&K, &V)> {
+        self.map.iter().map(|(k, v)| (k, &v.0)).collect()
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+        self.keys.shrink_to_fit();
+    }
+
+    fn lazy_clear(&self) -> bool {
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+}
+
+/// Cache evicting the least recently used entry
+pub struct LruCache<K, V> {
+    size: Option<usize>,
+    map: HashMap<K, (V, u64), PackratBuildHasher>,
+    keys: VecDeque<(K, u64)>,
+    stamp: u64,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        if self.size.is_some() {
+            if let Some((_, stamp)) = self.map.get_mut(key) {
+                self.stamp += 1;
+                *stamp = self.stamp;
+                self.keys.push_back((key.clone(), self.stamp));
+            }
+
+            // Drop keys refreshed after they were pushed
+            if self.keys.len() > self.map.len() * 2 {
+                let map = &self.map;
+                self.keys
+                    .retain(|(key, stamp)| map.get(key).is_some_and(|x| x.1 == *stamp));
+            }
+        }
+    }
+}
+
+impl<K, V> PackratCache<K, V> for LruCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn new(size: Option<usize>) -> Self {
+        let init_size = size.unwrap_or(0);
+        LruCache {
+            size,
+            map: HashMap::with_capacity_and_hasher(init_size, Default::default()),
+            keys: VecDeque::with_capacity(init_size),
+            stamp: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.touch(key);
+        self.map.get(key).map(|x| &x.0)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some((x, _)) = self.map.get_mut(&key) {
+            let value = std::mem::replace(x, value);
+            self.touch(&key);
+            return Some((key, value));
+        }
+
+        let mut evicted = None;
+        if let Some(size) = self.size {
+            if self.map.len() > size - 1 {
+                while let Some((key, stamp)) = self.keys.pop_front() {
+                    if self.map.get(&key).is_some_and(|x| x.1 == stamp) {
+                        evicted = self.map.remove_entry(&key).map(|(k, v)| (k, v.0));
+                        break;
+                    }
+                }
+            }
+        }
+        self.map.insert(key.clone(), (value, 0));
+        self.touch(&key);
+        evicted
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.map.remove(key).map(|x| x.0)
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.keys.clear();
+    }
+
+    fn drain(&mut self) -> Vec<(K, V)> {
+        self.keys.clear();
+        self.map.drain().map(|(k, v)| (k, v.0)).collect()
+    }
+
+    fn entries(&self) -> Vec<(&K, &V)> {
+        self.map.iter().map(|(k, v)| (k, &v.0)).collect()
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+        self.keys.shrink_to_fit();
+    }
+
+    fn lazy_clear(&self) -> bool {
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+}
+
+/// Cache indexed by parser and byte offset from the start of the input
+///
+/// Each parser id is numbered at the first insertion, and its entries are kept in a vector indexed by byte offset.
+/// The size limit is ignored, so the memory usage is proportional to the number of parsers times the input length.
+/// The positions out of the input range notified by `input_range` are not stored,
+/// so an unrelated input at a distant address doesn't extend the tables.
+pub struct DenseCache<K, V> {
+    ids: HashMap<u64, usize, PackratBuildHasher>,
+    tables: Vec<Vec<Option<(K, V)>>>,
+    base: usize,
+    range: Option<Range<usize>>,
+    len: usize,
+}
+
+impl<U, V> DenseCache<PackratKey<U>, V> {
+    fn index(&self, key: &PackratKey<U>) -> Option<(usize, usize)> {
+        let id = *self.ids.get(&key.id())?;
+        let offset = key.position().checked_sub(self.base)?;
+        Some((id, offset))
+    }
+}
+
+impl<U, V> PackratCache<PackratKey<U>, V> for DenseCache<PackratKey<U>, V>
+where
+    U: Eq,
+{
+    fn new(_size: Option<usize>) -> Self {
+        DenseCache {
+            ids: HashMap::default(),
+            tables: Vec::new(),
+            base: usize::MAX,
+            range: None,
+            len: 0,
+        }
+    }
+
+    fn get(&mut self, key: &PackratKey<U>) -> Option<&V> {
+        let (id, offset) = self.index(key)?;
+        match self.tables[id].get(offset) {
+            Some(Some((x, value))) if x == key => Some(value),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, key: PackratKey<U>, value: V) -> Option<(PackratKey<U>, V)> {
+        // The entry out of the input is evicted immediately
+        let position = key.position();
+        if let Some(range) = &self.range {
+            if position < range.start || position > range.end {
+                return Some((key, value));
+            }
+        }
+
+        // Extend the tables to the front if the position is before the start
+        if position < self.base {
+            if self.len != 0 {
+                let shift = self.base - position;
+                for table in &mut self.tables {
+                    table.splice(0..0, (0..shift).map(|_| None));
+                }
+            }
+            self.base = position;
+        }
+
+        let next_id = self.tables.len();
+        let id = *self.ids.entry(key.id()).or_insert(next_id);
+        if id == next_id {
+            self.tables.push(Vec::new());
+        }
+
+        let table = &mut self.tables[id];
+        let offset = position - self.base;
+        if table.len() <= offset {
+            table.resize_with(offset + 1, || None);
+        }
+        let replaced = table[offset].replace((key, value));
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        replaced
+    }
+
+    fn remove(&mut self, key: &PackratKey<U>) -> Option<V> {
+        let (id, offset) = self.index(key)?;
+        let slot = self.tables[id].get_mut(offset)?;
+        match slot {
+            Some((x, _)) if x == key => {
+                self.len -= 1;
+                slot.take().map(|x| x.1)
+            }
+            _ => None,
+        }
+    }
+
+    fn clear(&mut self) {
+        for table in &mut self.tables {
+            table.clear();
+        }
+        self.base = usize::MAX;
+        self.range = None;
+        self.len = 0;
+    }
+
+    fn drain(&mut self) -> Vec<(PackratKey<U>, V)> {
+        let ret = self
+            .tables
+            .iter_mut()
+            .flat_map(|table| table.drain(..).flatten())
+            .collect();
+        self.base = usize::MAX;
+        self.range = None;
+        self.len = 0;
+        ret
+    }
+
+    fn entries(&self) -> Vec<(&PackratKey<U>, &V)> {
+        self.tables
+            .iter()
+            .flat_map(|table| table.iter().flatten().map(|(k, v)| (k, v)))
+            .collect()
+    }
+
+    fn input_range(&mut self, range: Range<usize>) {
+        self.range = Some(range);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.ids.shrink_to_fit();
+        self.tables.shrink_to_fit();
+        for table in &mut self.tables {
+            table.shrink_to_fit();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
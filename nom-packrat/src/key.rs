@@ -0,0 +1,105 @@
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Key of packrat storage
+///
+/// Parser id and input position are packed into an integer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PackratKey<U> {
+    packed: u128,
+    pub extra: U,
+}
+
+impl<U> PackratKey<U> {
+    pub fn new(id: u64, position: usize, extra: U) -> Self {
+        PackratKey {
+            packed: (u128::from(id) << 64) | position as u128,
+            extra,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        (self.packed >> 64) as u64
+    }
+
+    pub fn position(&self) -> usize {
+        self.packed as u64 as usize
+    }
+}
+
+/// Numeric parser id derived from the parser name at compile time (FNV-1a)
+pub const fn rule_id(name: &str) -> u64 {
+    fnv1a(name.as_bytes())
+}
+
+/// FNV-1a hash of bytes, which is stable across process runs
+pub(crate) const fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Hash of input content used as a key of the results shared across parses
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = PackratHasher::default();
+    hasher.write_usize(bytes.len());
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Fast non-cryptographic hasher for packrat keys (FxHash)
+#[derive(Clone, Copy, Default)]
+pub struct PackratHasher {
+    hash: u64,
+}
+
+pub type PackratBuildHasher = BuildHasherDefault<PackratHasher>;
+
+impl PackratHasher {
+    fn add(&mut self, x: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ x).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for PackratHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut x = [0; 8];
+            x.copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(x));
+        }
+        for &x in chunks.remainder() {
+            self.add(u64::from(x));
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add(i);
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.add(i as u64);
+        self.add((i >> 64) as u64);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
@@ -29,27 +29,174 @@
 //!     println!("{:?}", result);
 //! }
 //! ```
+//!
+//! ## Streaming
+//!
+//! `nom::Err::Incomplete` is never stored because the result may change when more input arrives.
+//! So parsers built from `nom::*::streaming` can be used with `#[packrat_parser]`.
+//!
+//! The stored results are keyed by `PackratInput::packrat_position`.
+//! For `&str` and `&[T]` it is the address of the input, so if the buffer may be reallocated,
+//! `init!()` must be called before parsing again.
+//! For `LocatedSpan` it is the offset from the beginning, so the results can be reused
+//! after appending the next chunk even if the buffer is reallocated.
+//! On the other hand, every `LocatedSpan` starts at offset 0, so the results of the previous input
+//! are returned for another input unless `init!()` is called before parsing it.
+//!
+//! ```
+//! use nom::bytes::streaming::tag;
+//! use nom::IResult;
+//! use nom_packrat::{init, packrat_parser, storage};
+//!
+//! storage!(String);
+//!
+//! #[packrat_parser]
+//! pub fn parser(s: &str) -> IResult<&str, String> {
+//!     let (s, x) = tag("ab")(s)?;
+//!     Ok((s, x.to_string()))
+//! }
+//!
+//! fn main() {
+//!     // Reserve capacity to avoid reallocation
+//!     let mut buf = String::with_capacity(1024);
+//!     init!();
+//!
+//!     buf.push('a');
+//!     assert!(matches!(parser(&buf), Err(nom::Err::Incomplete(_))));
+//!
+//!     buf.push('b');
+//!     assert_eq!(parser(&buf), Ok(("", String::from("ab"))));
+//! }
+//! ```
+//!
+//! ## Explicit storage
+//!
+//! `#[packrat_parser(context)]` uses the storage carried by the input instead of the storage declared by `storage!`.
+//! So independent parses can be interleaved on the same thread, and `init!()` is not required.
+//!
+//! ```
+//! use nom::character::complete::char;
+//! use nom::IResult;
+//! use nom_locate::LocatedSpan;
+//! use nom_packrat::{packrat_parser, PackratStorage};
+//! use std::cell::RefCell;
+//!
+//! type Storage = PackratStorage<String, ()>;
+//! type Span<'a> = LocatedSpan<&'a str, &'a RefCell<Storage>>;
+//!
+//! #[packrat_parser(context)]
+//! pub fn parser(s: Span) -> IResult<Span, String> {
+//!     let (s, x) = char('a')(s)?;
+//!     Ok((s, x.to_string()))
+//! }
+//!
+//! fn main() {
+//!     let storage = RefCell::new(Storage::new(None));
+//!     let result = parser(LocatedSpan::new_extra("a", &storage));
+//!
+//!     assert_eq!(result.unwrap().1, "a");
+//!     assert_eq!(storage.borrow().len(), 1);
+//! }
+//! ```
+//!
+//! ## Persistence
+//!
+//! With the `serde` feature, `PackratStorage::save` exports the stored results as `PackratDump`,
+//! which can be serialized by any serde format, and `PackratStorage::load` imports them in another process run.
+//! The positions are saved as offsets from the beginning of the input, and the dump is loaded only if the content hash of the input matches.
+//! So the parse results of unchanged files can be reused at a cold start.
+//! The output type, the extra state and the error type must implement `Serialize` and `Deserialize`.
+//!
+//! ## Statistics
+//!
+//! With the `stats` feature, each storage counts cache hits, misses, stores and evictions of each parser.
+//! `PackratStorage::stats` returns the counters, and `PackratStats` is shown as a table by `Display`.
+//! Without the feature, the counters are not compiled.
+//!
+//! ```ignore
+//! PACKRAT_STORAGE.with(|storage| println!("{}", storage.borrow().stats()));
+//! ```
+//!
+//! ## Custom input
+//!
+//! Any input type can be used with `#[packrat_parser]` if it implements `PackratInput` and `HasExtraState`
+//! in addition to nom's input traits required by the parser.
+//! `PackratInput` gives the position used as a key, and skips the consumed length at cache hit.
+//! So a non-contiguous text storage like rope can be parsed and memoized by the global offset.
+//! See `examples/rope.rs` for the complete implementation.
+//!
+//! ```ignore
+//! impl<'a> PackratInput for Input<'a> {
+//!     fn packrat_position(&self) -> usize {
+//!         self.start
+//!     }
+//!
+//!     fn packrat_advance(&self, len: usize) -> Self {
+//!         self.slice(len..)
+//!     }
+//! }
+//!
+//! impl<'a> HasExtraState<()> for Input<'a> {
+//!     fn get_extra_state(&self) {}
+//! }
+//! ```
 
 extern crate nom_packrat_macros;
+use nom::error::{Error, ErrorKind, ParseError};
+use nom::{Offset, Slice};
 #[doc(inline)]
 pub use nom_packrat_macros::packrat_parser;
-use std::collections::{HashMap, VecDeque};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::{Range, RangeFrom};
+
+mod cache;
+mod key;
+#[cfg(feature = "serde")]
+mod persist;
+mod session;
+#[cfg(feature = "stats")]
+mod stats;
+pub use crate::cache::{DenseCache, FifoCache, LruCache, PackratCache};
+pub use crate::key::{content_hash, rule_id, PackratBuildHasher, PackratHasher, PackratKey};
+#[cfg(feature = "serde")]
+pub use crate::persist::PackratDump;
+pub use crate::session::{session, PackratSession};
+#[cfg(feature = "stats")]
+pub use crate::stats::{PackratRuleStats, PackratStats};
 
 /// Initialize packrat storage
 ///
 /// This must be called before each parsing.
 /// If this is not called, the parse result may be wrong.
+/// `session` or `PackratSession` can be used instead to initialize the storage automatically.
+/// The storage declared by `storage!(name = NAME, ..)` is initialized by `init!(NAME)`.
+/// The name is resolved as written, so the storage in other module or crate is initialized by the path like `init!(my_grammar::STORAGE)`.
 #[macro_export]
+#[allow(clippy::crate_in_macro_def)]
 macro_rules! init {
     () => {
         crate::PACKRAT_STORAGE.with(|storage| storage.borrow_mut().clear())
     };
+    ($($path:ident)::+) => {
+        $($path)::+.with(|storage| storage.borrow_mut().clear())
+    };
 }
 
 /// Declare packrat storage
 ///
 /// # Arguments
+/// * (Optional) `pub`: Make the storage public to be used by parsers and `init!` in other crates.
+/// * (Optional) `name = NAME`: A name of storage used by `#[packrat_parser(storage = NAME)]`.
+///   The default is `PACKRAT_STORAGE`, which is used by `#[packrat_parser]`.
+/// * (Optional) `error = V`: A type to store errors of parser. The type must implement `PackratError`.
+///   The default is `DiscardError`, and `OffsetError` or `Cloned` keep the original error.
+/// * (Optional) `cache = C`: A generic type of cache backend. `C<K, V>` must implement `PackratCache<K, V>`.
+///   The default is `FifoCache`, and `LruCache` keeps recently used entries when the storage is full.
+///   `DenseCache` is a table indexed by parser and byte offset without hashing input positions.
 /// * An output type of parser. The type must implement `Clone`.
 /// * (Optional) An extra key type. The type must implement `Eq + Hash + Clone`.
 /// * (Optional) Maximum entries of storage.
@@ -61,85 +208,795 @@ macro_rules! init {
 /// storage!(String, 1024);
 /// storage!(String, ExtraInfo);
 /// storage!(String, ExtraInfo, 1024);
+/// storage!(name = EXPR_STORAGE, ExprOutput);
+/// storage!(pub name = EXPR_STORAGE, ExprOutput);
+/// storage!(error = nom_packrat::OffsetError, String);
+/// storage!(error = nom_packrat::Cloned<CustomError>, String, 1024);
+/// storage!(cache = nom_packrat::LruCache, String, 1024);
+/// storage!(cache = nom_packrat::DenseCache, String);
+/// storage!(cache = custom::Cache, String);
 /// ```
 #[macro_export]
 macro_rules! storage {
-    ($t:ty) => {
-        thread_local!(
-            pub(crate) static PACKRAT_STORAGE: core::cell::RefCell<
-                nom_packrat::PackratStorage<$t, ()>
-            > = {
-                core::cell::RefCell::new(nom_packrat::PackratStorage::new(None))
-            }
-        );
+    (@impl [$($opts:tt)*] $t:ty) => {
+        $crate::storage!(@define [$t] [()] $($opts)* [None]);
     };
-    ($t:ty, $u:ty) => {
-        thread_local!(
-            pub(crate) static PACKRAT_STORAGE: core::cell::RefCell<
-                nom_packrat::PackratStorage<$t, $u>
-            > = {
-                core::cell::RefCell::new(nom_packrat::PackratStorage::new(None))
-            }
-        );
+    (@impl [$($opts:tt)*] $t:ty, $u:ty) => {
+        $crate::storage!(@define [$t] [$u] $($opts)* [None]);
     };
-    ($t:ty, $n:expr) => {
-        thread_local!(
-            pub(crate) static PACKRAT_STORAGE: core::cell::RefCell<
-                nom_packrat::PackratStorage<$t, ()>
-            > = {
-                core::cell::RefCell::new(nom_packrat::PackratStorage::new(Some($n)))
-            }
-        );
+    (@impl [$($opts:tt)*] $t:ty, $n:expr) => {
+        $crate::storage!(@define [$t] [()] $($opts)* [Some($n)]);
     };
-    ($t:ty, $u:ty, $n:expr) => {
+    (@impl [$($opts:tt)*] $t:ty, $u:ty, $n:expr) => {
+        $crate::storage!(@define [$t] [$u] $($opts)* [Some($n)]);
+    };
+    (@define [$t:ty] [$u:ty] [$($vis:tt)*] [$name:ident] [$e:ty] [$($c:tt)*] [$n:expr]) => {
         thread_local!(
-            pub(crate) static PACKRAT_STORAGE: core::cell::RefCell<
-                nom_packrat::PackratStorage<$t, $u>
+            $($vis)* static $name: core::cell::RefCell<
+                nom_packrat::PackratStorage<
+                    $t,
+                    $u,
+                    $e,
+                    $($c)*<nom_packrat::PackratKey<$u>, nom_packrat::PackratValue<$t, $e>>,
+                >
             > = {
-                core::cell::RefCell::new(nom_packrat::PackratStorage::new(Some($n)))
+                core::cell::RefCell::new(nom_packrat::PackratStorage::new($n))
             }
         );
     };
+    (@opts [$($vis:tt)*] [$name:ident] [$e:ty] [$($c:tt)*] pub $($rest:tt)*) => {
+        $crate::storage!(@opts [pub] [$name] [$e] [$($c)*] $($rest)*);
+    };
+    (@opts [$($vis:tt)*] [$name:ident] [$e:ty] [$($c:tt)*] name = $x:ident, $($rest:tt)*) => {
+        $crate::storage!(@opts [$($vis)*] [$x] [$e] [$($c)*] $($rest)*);
+    };
+    (@opts [$($vis:tt)*] [$name:ident] [$e:ty] [$($c:tt)*] error = $x:ty, $($rest:tt)*) => {
+        $crate::storage!(@opts [$($vis)*] [$name] [$x] [$($c)*] $($rest)*);
+    };
+    (@opts [$($vis:tt)*] [$name:ident] [$e:ty] [$($c:tt)*] cache = $($x:ident)::+, $($rest:tt)*) => {
+        $crate::storage!(@opts [$($vis)*] [$name] [$e] [$($x)::+] $($rest)*);
+    };
+    (@opts [$($vis:tt)*] [$name:ident] [$e:ty] [$($c:tt)*] $($rest:tt)*) => {
+        $crate::storage!(@impl [[$($vis)*] [$name] [$e] [$($c)*]] $($rest)*);
+    };
+    ($($rest:tt)*) => {
+        $crate::storage!(@opts [pub(crate)] [PACKRAT_STORAGE] [nom_packrat::DiscardError] [nom_packrat::FifoCache] $($rest)*);
+    };
+}
+
+/// Entry of packrat storage
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PackratEntry<T, E> {
+    /// The parser is running at the position
+    InProgress,
+    /// The parser succeeded with the output and the consumed length
+    Accepted(T, usize),
+    /// The parser failed with the recoverable error (`nom::Err::Error`)
+    Rejected(E),
+    /// The parser failed with the unrecoverable error (`nom::Err::Failure`)
+    Failure(E),
+}
+
+/// Error stored in packrat storage
+///
+/// `I` is an input type and `E` is an error type of parser.
+pub trait PackratError<I, E> {
+    /// Convert the error returned by the parser called at `input`
+    fn from_error(input: &I, error: &E) -> Self;
+
+    /// Restore the error for the parser called at `input`
+    fn to_error(&self, input: I) -> E;
+}
+
+/// Discard errors, and return `ErrorKind::Fix` at cache hit
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiscardError;
+
+impl<I, E> PackratError<I, E> for DiscardError
+where
+    E: ParseError<I>,
+{
+    fn from_error(_input: &I, _error: &E) -> Self {
+        DiscardError
+    }
+
+    fn to_error(&self, input: I) -> E {
+        E::from_error_kind(input, ErrorKind::Fix)
+    }
+}
+
+/// Keep the position and `ErrorKind` of `nom::error::Error`
+///
+/// It can't be serialized by the `serde` feature because `ErrorKind` doesn't implement serde traits.
+#[derive(Clone, Debug)]
+pub struct OffsetError {
+    pub offset: usize,
+    pub code: ErrorKind,
 }
 
-pub struct PackratStorage<T, U> {
-    size: Option<usize>,
-    map: HashMap<(&'static str, *const u8, U), Option<(T, usize)>>,
-    keys: VecDeque<(&'static str, *const u8, U)>,
+impl<I> PackratError<I, Error<I>> for OffsetError
+where
+    I: Offset + Slice<RangeFrom<usize>>,
+{
+    fn from_error(input: &I, error: &Error<I>) -> Self {
+        OffsetError {
+            offset: input.offset(&error.input),
+            code: error.code,
+        }
+    }
+
+    fn to_error(&self, input: I) -> Error<I> {
+        Error::new(input.slice(self.offset..), self.code)
+    }
 }
 
-impl<T, U> PackratStorage<T, U>
+/// Keep the error of parser as is
+///
+/// The error type must implement `Clone` and must not borrow the input.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cloned<E>(pub E);
+
+impl<I, E> PackratError<I, E> for Cloned<E>
+where
+    E: Clone,
+{
+    fn from_error(_input: &I, error: &E) -> Self {
+        Cloned(error.clone())
+    }
+
+    fn to_error(&self, _input: I) -> E {
+        self.0.clone()
+    }
+}
+
+/// Entry of packrat storage with the generation when it was stored
+#[derive(Clone, Debug)]
+pub struct PackratValue<T, E> {
+    entry: PackratEntry<T, E>,
+    generation: u64,
+}
+
+/// Point of the storage to be restored by `PackratStorage::rollback`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackratSnapshot {
+    log: usize,
+    generation: u64,
+}
+
+/// Key changed after a snapshot, and the entry before the change
+type Undo<T, U, E> = (PackratKey<U>, Option<PackratValue<T, E>>);
+
+/// Shared results keyed by parser id, extra state and content hash of the remaining input
+type SharedCache<T, U, E> = FifoCache<(u64, U, u64), (Box<[u8]>, PackratEntry<T, E>)>;
+
+/// Stale entries of previous generations are dropped at once if they are more than this
+const LAZY_CLEAR_LIMIT: usize = 4096;
+
+/// Remaining inputs longer than this are not shared not to hash them at each cache miss
+const SHARED_LEN_LIMIT: usize = 1024;
+
+pub struct PackratStorage<T, U, E = DiscardError, C = FifoCache<PackratKey<U>, PackratValue<T, E>>>
+{
+    cache: C,
+    // Entries of running parsers are kept out of the cache not to be evicted
+    running: HashMap<PackratKey<U>, PackratEntry<T, E>, PackratBuildHasher>,
+    growing: Vec<(PackratKey<U>, Vec<PackratKey<U>>)>,
+    // Running parsers rejected as involved in seed growing
+    cut: Vec<PackratKey<U>>,
+    shared: SharedCache<T, U, E>,
+    undo: Vec<Undo<T, U, E>>,
+    snapshots: usize,
+    #[cfg(feature = "stats")]
+    stats: HashMap<u64, PackratRuleStats, PackratBuildHasher>,
+    range: Option<(usize, usize)>,
+    sessions: usize,
+    generation: u64,
+    live: usize,
+    _phantom: PhantomData<(T, E)>,
+}
+
+impl<T, U, E, C> PackratStorage<T, U, E, C>
 where
     U: Eq + Hash + Clone,
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
 {
     pub fn new(size: Option<usize>) -> Self {
-        let init_size = size.unwrap_or_else(|| 0);
         PackratStorage {
-            size,
-            map: HashMap::with_capacity(init_size),
-            keys: VecDeque::with_capacity(init_size),
+            cache: C::new(size),
+            running: HashMap::default(),
+            growing: Vec::new(),
+            cut: Vec::new(),
+            shared: FifoCache::new(size),
+            undo: Vec::new(),
+            snapshots: 0,
+            #[cfg(feature = "stats")]
+            stats: HashMap::default(),
+            range: None,
+            sessions: 0,
+            generation: 0,
+            live: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn with_cache(cache: C) -> Self {
+        PackratStorage {
+            cache,
+            running: HashMap::default(),
+            growing: Vec::new(),
+            cut: Vec::new(),
+            shared: FifoCache::new(None),
+            undo: Vec::new(),
+            snapshots: 0,
+            #[cfg(feature = "stats")]
+            stats: HashMap::default(),
+            range: None,
+            sessions: 0,
+            generation: 0,
+            live: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn get(&mut self, key: &PackratKey<U>) -> Option<&PackratEntry<T, E>> {
+        Self::find(&self.running, &mut self.cache, self.generation, key)
+    }
+
+    // The fields are borrowed separately to update the others while the entry is borrowed
+    fn find<'a>(
+        running: &'a HashMap<PackratKey<U>, PackratEntry<T, E>, PackratBuildHasher>,
+        cache: &'a mut C,
+        generation: u64,
+        key: &PackratKey<U>,
+    ) -> Option<&'a PackratEntry<T, E>> {
+        if let Some(x) = running.get(key) {
+            return Some(x);
+        }
+        match cache.get(key) {
+            Some(x) if x.generation == generation => Some(&x.entry),
+            _ => None,
+        }
+    }
+
+    /// Get the entry stored by the parser named `name`
+    ///
+    /// The hit or miss is counted with the `stats` feature.
+    pub fn lookup(
+        &mut self,
+        key: &PackratKey<U>,
+        name: &'static str,
+    ) -> Option<&PackratEntry<T, E>> {
+        let entry = Self::find(&self.running, &mut self.cache, self.generation, key);
+
+        #[cfg(feature = "stats")]
+        {
+            // `entry` borrows the storage, so the counters are accessed without `rule_stats`
+            let stats = self.stats.entry(key.id()).or_default();
+            stats.name = name;
+            match entry {
+                Some(PackratEntry::Accepted(..)) => stats.accepted_hits += 1,
+                Some(_) => stats.rejected_hits += 1,
+                None => stats.misses += 1,
+            }
+        }
+        #[cfg(not(feature = "stats"))]
+        let _ = name;
+
+        entry
+    }
+
+    /// Store the result of the parser named `name`
+    ///
+    /// The store is counted with the `stats` feature.
+    /// The entries put by `insert` directly like the ones loaded or shifted by edit are not counted.
+    /// The results at the position of a parser rejected by `reject_running` depend on the rejection,
+    /// so they are not stored until the parser returns, and the result of the parser is not stored either.
+    pub fn store(&mut self, key: PackratKey<U>, value: PackratEntry<T, E>, name: &'static str) {
+        if self.cut.iter().any(|x| x.position() == key.position()) {
+            self.cut.retain(|x| *x != key);
+            self.running.remove(&key);
+            return;
+        }
+
+        #[cfg(feature = "stats")]
+        {
+            if !matches!(value, PackratEntry::InProgress) {
+                let stats = self.rule_stats(key.id());
+                stats.name = name;
+                stats.stores += 1;
+            }
+        }
+        #[cfg(not(feature = "stats"))]
+        let _ = name;
+
+        self.insert(key, value);
+    }
+
+    pub fn insert(&mut self, key: PackratKey<U>, value: PackratEntry<T, E>) {
+        // The mark of running parser must not be evicted to detect left recursion
+        if let PackratEntry::InProgress = value {
+            self.running.insert(key, value);
+            return;
+        }
+        if !self.running.is_empty() {
+            self.running.remove(&key);
+        }
+
+        // Results of other parsers at the growing position depend on the current seed
+        for (seed, involved) in &mut self.growing {
+            if seed.position() == key.position() && seed.id() != key.id() {
+                involved.push(key.clone());
+            }
+        }
+
+        let value = PackratValue {
+            entry: value,
+            generation: self.generation,
+        };
+        // The key is kept to distinguish the replaced entry from the evicted one
+        let inserted = if self.snapshots > 0 || cfg!(feature = "stats") {
+            Some(key.clone())
+        } else {
+            None
+        };
+        self.live += 1;
+        let mut replaced = None;
+        if let Some((k, x)) = self.cache.insert(key, value) {
+            if x.generation == self.generation {
+                self.live -= 1;
+                if inserted.as_ref() == Some(&k) {
+                    replaced = Some(x);
+                } else {
+                    // An evicted entry is not restored by rollback because it may be evicted again
+                    // The rules never looked up or stored have no stats to be shown with the name
+                    #[cfg(feature = "stats")]
+                    {
+                        if !matches!(x.entry, PackratEntry::InProgress) {
+                            if let Some(stats) = self.stats.get_mut(&k.id()) {
+                                stats.evictions += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if self.snapshots > 0 {
+            if let Some(key) = inserted {
+                self.undo.push((key, replaced));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &PackratKey<U>) {
+        if !self.running.is_empty() {
+            self.running.remove(key);
+        }
+        if !self.cut.is_empty() {
+            self.cut.retain(|x| x != key);
+        }
+        if let Some(x) = self.cache.remove(key) {
+            if x.generation == self.generation {
+                self.live -= 1;
+                if self.snapshots > 0 {
+                    self.undo.push((key.clone(), Some(x)));
+                }
+            }
         }
     }
 
-    pub fn get(&self, key: &(&'static str, *const u8, U)) -> Option<&Option<(T, usize)>> {
-        self.map.get(key)
+    /// Start recording changes to be discarded by `rollback`
+    ///
+    /// Each snapshot must be finished by `rollback` or `commit` in the reverse order of creation.
+    pub fn snapshot(&mut self) -> PackratSnapshot {
+        self.snapshots += 1;
+        PackratSnapshot {
+            log: self.undo.len(),
+            generation: self.generation,
+        }
     }
 
-    pub fn insert(&mut self, key: (&'static str, *const u8, U), value: Option<(T, usize)>) {
-        if let Some(size) = self.size {
-            if self.keys.len() > size - 1 {
-                let key = self.keys.pop_front().unwrap();
-                self.map.remove(&key);
+    /// Discard the entries stored or removed after `snapshot`
+    ///
+    /// The changes are undone in reverse order by the log, so the cost is proportional to the changes after `snapshot`.
+    pub fn rollback(&mut self, snapshot: PackratSnapshot) {
+        // If the storage was cleared after `snapshot`, the log has only the changes after the clear
+        let log = if snapshot.generation == self.generation {
+            snapshot.log
+        } else {
+            0
+        };
+        while self.undo.len() > log {
+            let (key, old) = self.undo.pop().unwrap();
+            if let Some(x) = self.cache.remove(&key) {
+                if x.generation == self.generation {
+                    self.live -= 1;
+                }
+            }
+            if let Some(x) = old {
+                self.live += 1;
+                if let Some((_, x)) = self.cache.insert(key, x) {
+                    if x.generation == self.generation {
+                        self.live -= 1;
+                    }
+                }
             }
         }
+        self.commit(snapshot);
+    }
+
+    /// Keep the changes after `snapshot`, and stop recording them if it is the outermost snapshot
+    pub fn commit(&mut self, _snapshot: PackratSnapshot) {
+        self.snapshots = self.snapshots.saturating_sub(1);
+        if self.snapshots == 0 {
+            self.undo.clear();
+        }
+    }
+
+    /// Get the result shared across parses for the remaining input whose content is `bytes`
+    ///
+    /// The remaining input is hashed, and the content is compared at hit.
+    /// The remaining input longer than `SHARED_LEN_LIMIT` is not shared.
+    pub fn get_shared(&mut self, key: &PackratKey<U>, bytes: &[u8]) -> Option<&PackratEntry<T, E>> {
+        if bytes.len() > SHARED_LEN_LIMIT {
+            return None;
+        }
+        let shared_key = (key.id(), key.extra.clone(), content_hash(bytes));
+        match self.shared.get(&shared_key) {
+            Some((x, entry)) if **x == *bytes => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Share the result for the remaining input whose content is `bytes`
+    ///
+    /// A parser may examine the whole remaining input by lookahead, so the result is keyed by it.
+    /// The result during seed growing at the same position depends on the seed, so it is not shared.
+    pub fn insert_shared(&mut self, key: &PackratKey<U>, bytes: &[u8], value: PackratEntry<T, E>) {
+        if bytes.len() > SHARED_LEN_LIMIT || matches!(value, PackratEntry::InProgress) {
+            return;
+        }
+        if self
+            .growing
+            .iter()
+            .map(|(x, _)| x)
+            .chain(&self.cut)
+            .any(|x| x.position() == key.position())
+        {
+            return;
+        }
 
-        self.keys.push_back(key.clone());
-        self.map.insert(key, value);
+        let shared_key = (key.id(), key.extra.clone(), content_hash(bytes));
+        self.shared.insert(shared_key, (bytes.into(), value));
     }
 
+    /// Drop the shared results, which are kept by `clear` and `release`
+    pub fn clear_shared(&mut self) {
+        self.shared.clear();
+        self.shared.shrink_to_fit();
+    }
+
+    pub fn shared_len(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Offset of the position from the beginning of the input checked after the last clear
+    pub fn offset(&self, position: usize) -> usize {
+        position.saturating_sub(self.range.map_or(0, |x| x.0))
+    }
+
+    /// Check that the input from `position` with `len` is a part of the input parsed after the last clear
+    ///
+    /// If it is out of the range, the stored results are stale because `init!()` was not called.
+    /// So the storage is cleared.
+    /// It is checked only when no parser is running, so another input parsed inside a parser
+    /// doesn't clear the results of the running parsers.
+    /// A new input at the same position as the previous one can't be detected,
+    /// so `LocatedSpan` whose position always starts from 0 is not checked effectively.
+    pub fn check_input(&mut self, position: usize, len: usize) {
+        if !self.running.is_empty() || !self.growing.is_empty() {
+            return;
+        }
+
+        let end = position.saturating_add(len);
+        match self.range {
+            Some((start, x)) if start <= position && end <= x => return,
+            // The input was appended by streaming
+            Some((start, x)) if start <= position && position <= x => {
+                self.range = Some((start, end));
+            }
+            Some(_) => {
+                self.clear();
+                self.range = Some((position, end));
+            }
+            None => self.range = Some((position, end)),
+        }
+        if let Some((start, end)) = self.range {
+            self.cache.input_range(start..end);
+        }
+    }
+
+    /// Start seed growing of a left-recursive parser
+    ///
+    /// The seed is a failure, so the recursive call at the same position is rejected.
+    /// The seed is kept out of the cache, so it is not evicted by the size limit.
+    pub fn begin_growing(&mut self, key: PackratKey<U>) {
+        self.running.insert(key.clone(), PackratEntry::InProgress);
+        self.growing.push((key, Vec::new()));
+    }
+
+    /// Reject the running parser called again at the position of a growing seed
+    ///
+    /// The parser is involved in the left recursion of the seed, so the call fails like the seed
+    /// instead of being detected as unexpected left recursion.
+    /// Return `false` if no seed is growing at the position.
+    pub fn reject_running(&mut self, key: &PackratKey<U>) -> bool {
+        if self
+            .growing
+            .iter()
+            .all(|(x, _)| x.position() != key.position())
+        {
+            return false;
+        }
+        if !self.cut.contains(key) {
+            self.cut.push(key.clone());
+        }
+        true
+    }
+
+    /// Replace the seed of the innermost left-recursive parser,
+    /// and drop the results which were derived from the previous seed
+    pub fn grow(&mut self, seed: PackratEntry<T, E>) {
+        if let Some((key, involved)) = self.growing.last_mut() {
+            self.running.insert(key.clone(), seed);
+            for key in std::mem::take(involved) {
+                self.remove(&key);
+            }
+        }
+    }
+
+    /// Finish seed growing of the innermost left-recursive parser
+    pub fn end_growing(&mut self) {
+        if let Some((key, _)) = self.growing.pop() {
+            self.running.remove(&key);
+        }
+    }
+
+    /// Clear the storage in constant time if the cache supports `PackratCache::lazy_clear`
+    ///
+    /// The stored entries are treated as absent by bumping the generation, and overwritten lazily.
     pub fn clear(&mut self) {
-        self.map.clear();
-        self.keys.clear();
+        if !self.cache.lazy_clear() || self.cache.len() > LAZY_CLEAR_LIMIT {
+            self.cache.clear();
+        }
+        self.generation += 1;
+        self.live = 0;
+        self.running.clear();
+        self.growing.clear();
+        self.cut.clear();
+        self.undo.clear();
+        self.range = None;
+    }
+
+    /// Update the stored results for the edit replacing `range` of the input with `new_len` length
+    ///
+    /// The positions of the results after the edited range are shifted, and they are reused by reparsing after the edit.
+    /// The positions must be offsets from the beginning of the input like `LocatedSpan`.
+    /// A result before the edited range may have examined it by lookahead like `alt`, `not` or `peek`
+    /// even if the consumed input ends before it, so all results before the edited range are dropped.
+    pub fn apply_edit(&mut self, range: Range<usize>, new_len: usize) {
+        // The kept entries are stored again in the next generation,
+        // so rolling back a snapshot before the edit discards all of them
+        let entries = self.cache.drain();
+        let generation = self.generation;
+        self.generation += 1;
+        self.live = 0;
+        self.growing.clear();
+        self.cut.clear();
+        self.undo.clear();
+        self.range = None;
+
+        for (key, value) in entries {
+            if value.generation != generation {
+                continue;
+            }
+
+            // A parser examines only the input after its position
+            let position = key.position();
+            if position >= range.end && position > range.start {
+                let position = position - range.end + range.start + new_len;
+                let key = PackratKey::new(key.id(), position, key.extra);
+                self.insert(key, value.entry);
+            }
+        }
+    }
+
+    /// Drop all entries immediately, and release the memory
+    ///
+    /// The shared results are kept to be reused by the next parse.
+    pub fn release(&mut self) {
+        self.clear();
+        self.cache.clear();
+        self.cache.shrink_to_fit();
+    }
+
+    /// Start a parse session, and clear the storage if it is the outermost session
+    pub fn begin_session(&mut self) {
+        if self.sessions == 0 {
+            self.clear();
+        }
+        self.sessions += 1;
+    }
+
+    /// Finish a parse session, and release the memory if it is the outermost session
+    pub fn end_session(&mut self) {
+        self.sessions = self.sessions.saturating_sub(1);
+        if self.sessions == 0 {
+            self.release();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    /// Counters of each parser collected after the last `clear_stats`
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> PackratStats {
+        let mut rules: Vec<_> = self.stats.values().cloned().collect();
+        rules.sort_by(|x, y| x.name.cmp(y.name));
+        PackratStats { rules }
+    }
+
+    /// Reset the counters, which are kept by `clear` and `release`
+    #[cfg(feature = "stats")]
+    pub fn clear_stats(&mut self) {
+        self.stats.clear();
+    }
+
+    #[cfg(feature = "stats")]
+    fn rule_stats(&mut self, id: u64) -> &mut PackratRuleStats {
+        self.stats.entry(id).or_default()
+    }
+
+    /// Cache backend of the storage
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+}
+
+impl<T, U, E, C> PackratStorage<T, U, E, C>
+where
+    T: Clone,
+    U: Eq + Hash + Clone,
+    E: Clone,
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
+{
+    /// Get the entry stored by the shared parser named `name`, or the result shared for the remaining input `bytes`
+    ///
+    /// The shared result is copied to the storage to be found at the position, and counted as a hit with the `stats` feature.
+    pub fn lookup_shared(
+        &mut self,
+        key: &PackratKey<U>,
+        bytes: &[u8],
+        name: &'static str,
+    ) -> Option<&PackratEntry<T, E>> {
+        if Self::find(&self.running, &mut self.cache, self.generation, key).is_none() {
+            if let Some(x) = self.get_shared(key, bytes).cloned() {
+                self.insert(key.clone(), x);
+            }
+        }
+        self.lookup(key, name)
+    }
+}
+
+// The storage is shown in errors of `#[packrat_parser(context)]` input, so the entries are omitted
+impl<T, U, E, C> fmt::Debug for PackratStorage<T, U, E, C>
+where
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PackratStorage")
+            .field("len", &self.live)
+            .finish()
+    }
+}
+
+/// Input type which can be used by packrat parser
+pub trait PackratInput: Sized {
+    /// Position of the input used as a key of packrat storage
+    ///
+    /// Positions must increase as the input is consumed, and the difference of two positions
+    /// must be the consumed length, which is computed by wrapping subtraction.
+    fn packrat_position(&self) -> usize;
+
+    /// Skip the consumed length of the stored result
+    fn packrat_advance(&self, len: usize) -> Self;
+}
+
+impl PackratInput for &str {
+    fn packrat_position(&self) -> usize {
+        self.as_ptr() as usize
+    }
+
+    fn packrat_advance(&self, len: usize) -> Self {
+        &self[len..]
+    }
+}
+
+/// The position of slice is the index of element, so `&[Token]` produced by a lexer can be used
+impl<T> PackratInput for &[T] {
+    fn packrat_position(&self) -> usize {
+        self.as_ptr() as usize / std::mem::size_of::<T>().max(1)
+    }
+
+    fn packrat_advance(&self, len: usize) -> Self {
+        &self[len..]
+    }
+}
+
+/// The position of bit-level input is the bit offset, and the consumed length is counted in bits
+///
+/// The bit offset of an address can exceed `usize` on 32-bit targets, so it wraps around.
+impl PackratInput for (&[u8], usize) {
+    fn packrat_position(&self) -> usize {
+        (self.0.as_ptr() as usize)
+            .wrapping_mul(8)
+            .wrapping_add(self.1)
+    }
+
+    fn packrat_advance(&self, len: usize) -> Self {
+        let bit = self.1 + len;
+        (&self.0[bit / 8..], bit % 8)
+    }
+}
+
+/// The position of `LocatedSpan` is the offset from the beginning of the original input
+impl<T, X> PackratInput for nom_locate::LocatedSpan<T, X>
+where
+    Self: Slice<RangeFrom<usize>>,
+{
+    fn packrat_position(&self) -> usize {
+        self.location_offset()
+    }
+
+    fn packrat_advance(&self, len: usize) -> Self {
+        self.slice(len..)
+    }
+}
+
+/// Input type carrying packrat storage for `#[packrat_parser(context)]`
+///
+/// `LocatedSpan` whose `extra` is `&RefCell<PackratStorage<..>>` implements this trait.
+/// A custom `extra` type can carry both the storage and the extra state by implementing this trait and `HasExtraState`.
+pub trait PackratContext {
+    type Storage;
+
+    fn packrat_storage(&self) -> &RefCell<Self::Storage>;
+}
+
+impl<S> PackratContext for &RefCell<S> {
+    type Storage = S;
+
+    fn packrat_storage(&self) -> &RefCell<S> {
+        self
+    }
+}
+
+impl<T, X> PackratContext for nom_locate::LocatedSpan<T, X>
+where
+    X: PackratContext,
+{
+    type Storage = X::Storage;
+
+    fn packrat_storage(&self) -> &RefCell<Self::Storage> {
+        self.extra.packrat_storage()
     }
 }
 
@@ -148,21 +1005,23 @@ pub trait HasExtraState<T> {
 }
 
 impl HasExtraState<()> for &str {
-    fn get_extra_state(&self) -> () {
-        ()
-    }
+    fn get_extra_state(&self) {}
 }
 
-impl HasExtraState<()> for &[u8] {
-    fn get_extra_state(&self) -> () {
-        ()
-    }
+impl<T> HasExtraState<()> for &[T] {
+    fn get_extra_state(&self) {}
+}
+
+impl HasExtraState<()> for (&[u8], usize) {
+    fn get_extra_state(&self) {}
+}
+
+impl<S> HasExtraState<()> for &RefCell<S> {
+    fn get_extra_state(&self) {}
 }
 
 impl<T> HasExtraState<()> for nom_locate::LocatedSpan<T, ()> {
-    fn get_extra_state(&self) -> () {
-        ()
-    }
+    fn get_extra_state(&self) {}
 }
 
 impl<T, U, V> HasExtraState<T> for nom_locate::LocatedSpan<U, V>
@@ -0,0 +1,117 @@
+use crate::key::fnv1a;
+use crate::{PackratCache, PackratEntry, PackratInput, PackratKey, PackratStorage, PackratValue};
+use nom::{AsBytes, InputLength};
+use serde::{Deserialize, Serialize};
+use std::hash::Hash;
+
+/// Stored results detached from the input address, which can be serialized by serde
+///
+/// The positions are offsets from the beginning of the input,
+/// and the input is identified by the length and the content hash.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PackratDump<T, U, E> {
+    hash: u64,
+    len: usize,
+    entries: Vec<(u64, usize, U, PackratEntry<T, E>)>,
+}
+
+impl<T, U, E> PackratDump<T, U, E> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T, U, E, C> PackratStorage<T, U, E, C>
+where
+    T: Clone,
+    U: Eq + Hash + Clone,
+    E: Clone,
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
+{
+    /// Export the stored results of parsing `input`
+    ///
+    /// `input` must be the whole input given to the outermost parser.
+    /// The results out of `input` and the parsers running now are not exported.
+    ///
+    /// ```
+    /// # use nom::character::complete::char;
+    /// # use nom::IResult;
+    /// # use nom_packrat::{init, packrat_parser, storage};
+    /// # storage!(String);
+    /// # #[packrat_parser]
+    /// # pub fn parser(s: &str) -> IResult<&str, String> {
+    /// #     let (s, x) = char('a')(s)?;
+    /// #     Ok((s, x.to_string()))
+    /// # }
+    /// # fn main() {
+    /// let input = "a";
+    /// init!();
+    /// parser(input).unwrap();
+    ///
+    /// let dump = PACKRAT_STORAGE.with(|s| s.borrow().save(&input));
+    /// let json = serde_json::to_string(&dump).unwrap();
+    ///
+    /// // The results can be restored for the same content at another address
+    /// let input = String::from("a");
+    /// init!();
+    /// let loaded = PACKRAT_STORAGE.with(|s| s.borrow_mut().load(&input.as_str(), serde_json::from_str(&json).unwrap()));
+    /// assert!(loaded);
+    /// assert_eq!(PACKRAT_STORAGE.with(|s| s.borrow().len()), 1);
+    /// # }
+    /// ```
+    pub fn save<I>(&self, input: &I) -> PackratDump<T, U, E>
+    where
+        I: PackratInput + AsBytes + InputLength,
+    {
+        let start = input.packrat_position();
+        let end = start + input.input_len();
+
+        let mut dump = Vec::new();
+        for (key, value) in self.cache.entries() {
+            if value.generation != self.generation {
+                continue;
+            }
+
+            let position = key.position();
+            let stable = !matches!(value.entry, PackratEntry::InProgress);
+            if stable && start <= position && position <= end {
+                dump.push((
+                    key.id(),
+                    position - start,
+                    key.extra.clone(),
+                    value.entry.clone(),
+                ));
+            }
+        }
+
+        PackratDump {
+            hash: fnv1a(input.as_bytes()),
+            len: input.input_len(),
+            entries: dump,
+        }
+    }
+
+    /// Import the results exported by `save` for `input`
+    ///
+    /// If the length or the content hash of `input` differs from the saved one, nothing is imported and `false` is returned.
+    /// The storage is not cleared, so `init!()` should be called before this.
+    pub fn load<I>(&mut self, input: &I, dump: PackratDump<T, U, E>) -> bool
+    where
+        I: PackratInput + AsBytes + InputLength,
+    {
+        if dump.len != input.input_len() || dump.hash != fnv1a(input.as_bytes()) {
+            return false;
+        }
+
+        let start = input.packrat_position();
+        self.check_input(start, dump.len);
+        for (id, offset, extra, entry) in dump.entries {
+            self.insert(PackratKey::new(id, start + offset, extra), entry);
+        }
+        true
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{PackratCache, PackratKey, PackratStorage, PackratValue};
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::thread::LocalKey;
+
+type Storage<T, U, E, C> = &'static LocalKey<RefCell<PackratStorage<T, U, E, C>>>;
+
+/// Guard of parse session
+///
+/// The storage is cleared when the guard is created, and the memory is released when it is dropped.
+/// Nested sessions share the storage of the outermost session.
+///
+/// ```
+/// use nom::character::complete::char;
+/// use nom::IResult;
+/// use nom_packrat::{packrat_parser, storage, PackratSession};
+///
+/// storage!(String);
+///
+/// #[packrat_parser]
+/// pub fn parser(s: &str) -> IResult<&str, String> {
+///     let (s, x) = char('a')(s)?;
+///     Ok((s, x.to_string()))
+/// }
+///
+/// fn main() {
+///     let session = PackratSession::new(&PACKRAT_STORAGE);
+///     let result = parser("a");
+///     drop(session);
+///
+///     assert_eq!(result, Ok(("", String::from("a"))));
+/// }
+/// ```
+pub struct PackratSession<T: 'static, U: 'static, E: 'static, C: 'static>
+where
+    U: Eq + Hash + Clone,
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
+{
+    storage: Storage<T, U, E, C>,
+}
+
+impl<T, U, E, C> PackratSession<T, U, E, C>
+where
+    U: Eq + Hash + Clone,
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
+{
+    pub fn new(storage: Storage<T, U, E, C>) -> Self {
+        storage.with(|storage| storage.borrow_mut().begin_session());
+        PackratSession { storage }
+    }
+}
+
+impl<T, U, E, C> Drop for PackratSession<T, U, E, C>
+where
+    U: Eq + Hash + Clone,
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
+{
+    fn drop(&mut self) {
+        // The storage may be already destroyed at thread exit
+        let _ = self
+            .storage
+            .try_with(|storage| storage.borrow_mut().end_session());
+    }
+}
+
+/// Call `f` in a parse session of `storage`
+///
+/// ```
+/// # use nom::character::complete::char;
+/// # use nom::IResult;
+/// # use nom_packrat::{packrat_parser, storage};
+/// # storage!(String);
+/// # #[packrat_parser]
+/// # pub fn parser(s: &str) -> IResult<&str, String> {
+/// #     let (s, x) = char('a')(s)?;
+/// #     Ok((s, x.to_string()))
+/// # }
+/// # fn main() {
+/// let result = nom_packrat::session(&PACKRAT_STORAGE, || parser("a"));
+/// assert_eq!(result, Ok(("", String::from("a"))));
+/// # }
+/// ```
+pub fn session<T, U, E, C, R, F>(storage: Storage<T, U, E, C>, f: F) -> R
+where
+    U: Eq + Hash + Clone,
+    C: PackratCache<PackratKey<U>, PackratValue<T, E>>,
+    F: FnOnce() -> R,
+{
+    let _session = PackratSession::new(storage);
+    f()
+}
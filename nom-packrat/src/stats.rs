@@ -0,0 +1,57 @@
+use std::fmt;
+
+/// Counters of a parser collected by the `stats` feature
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackratRuleStats {
+    /// Parser name qualified by module path
+    pub name: &'static str,
+    /// Cache hits returning the stored output
+    pub accepted_hits: u64,
+    /// Cache hits returning the stored error
+    pub rejected_hits: u64,
+    /// Cache misses which run the parser
+    pub misses: u64,
+    /// Results stored after running the parser
+    pub stores: u64,
+    /// Results evicted by the size limit of storage
+    pub evictions: u64,
+}
+
+/// Counters of all parsers using the storage, sorted by parser name
+///
+/// `Display` shows them as a table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackratStats {
+    pub rules: Vec<PackratRuleStats>,
+}
+
+impl fmt::Display for PackratStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.rules.iter().map(|x| x.name.len()).fold(4, usize::max);
+        writeln!(
+            f,
+            "{:<width$} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "rule",
+            "accepted",
+            "rejected",
+            "misses",
+            "stores",
+            "evictions",
+            width = width
+        )?;
+        for x in &self.rules {
+            writeln!(
+                f,
+                "{:<width$} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                x.name,
+                x.accepted_hits,
+                x.rejected_hits,
+                x.misses,
+                x.stores,
+                x.evictions,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
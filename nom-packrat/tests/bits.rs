@@ -0,0 +1,38 @@
+use nom::bits::complete::{tag, take};
+use nom::branch::alt;
+use nom::sequence::preceded;
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, storage};
+
+storage!(u8);
+
+type Bits<'a> = (&'a [u8], usize);
+
+#[packrat_parser]
+pub fn field(s: Bits) -> IResult<Bits, u8> {
+    let (s, x): (_, u8) = take(3usize)(s)?;
+    Ok((s, x))
+}
+
+#[packrat_parser]
+pub fn packet(s: Bits) -> IResult<Bits, u8> {
+    alt((
+        preceded(tag(0b0, 1usize), field),
+        preceded(tag(0b1, 1usize), field),
+    ))(s)
+}
+
+#[test]
+fn bit_input() {
+    let input = [0b1010_1100, 0b1111_0000];
+
+    init!();
+    assert_eq!(Ok(((&input[..], 4), 0b010)), packet((&input, 0)));
+
+    // Cache hit at the middle of byte
+    assert_eq!(Ok(((&input[..], 4), 0b010)), field((&input, 1)));
+
+    // Cache hit across the byte boundary
+    assert_eq!(Ok(((&input[1..], 1), 0b001)), field((&input, 6)));
+    assert_eq!(Ok(((&input[1..], 1), 0b001)), field((&input, 6)));
+}
@@ -0,0 +1,117 @@
+use nom::character::complete::*;
+use nom::IResult;
+use nom_packrat::{
+    init, packrat_parser, rule_id, storage, FifoCache, LruCache, PackratCache, PackratKey,
+};
+use std::hash::Hash;
+
+// Cache backend counting hits
+pub struct CountingCache<K, V> {
+    cache: FifoCache<K, V>,
+    hits: usize,
+}
+
+impl<K, V> PackratCache<K, V> for CountingCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn new(size: Option<usize>) -> Self {
+        CountingCache {
+            cache: FifoCache::new(size),
+            hits: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let ret = self.cache.get(key);
+        if ret.is_some() {
+            self.hits += 1;
+        }
+        ret
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.cache.insert(key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.cache.remove(key)
+    }
+
+    fn clear(&mut self) {
+        self.cache.clear();
+        self.hits = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+}
+
+storage!(cache = CountingCache, String);
+
+#[packrat_parser]
+pub fn p1(s: &str) -> IResult<&str, String> {
+    let (s, x) = char('a')(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn custom_cache() {
+    init!();
+    let input = "a";
+    assert_eq!(Ok(("", String::from("a"))), p1(input));
+    assert_eq!(Ok(("", String::from("a"))), p1(input));
+    assert_eq!(Ok(("", String::from("a"))), p1(input));
+
+    PACKRAT_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        assert_eq!(1, storage.len());
+        assert_eq!(2, storage.cache().hits);
+    });
+
+    // The custom cache is cleared by `init!()` without lazy clear
+    init!();
+    PACKRAT_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        assert_eq!(0, storage.cache().len());
+        assert_eq!(0, storage.cache().hits);
+    });
+}
+
+#[test]
+fn lru_cache() {
+    let mut cache = LruCache::new(Some(2));
+    assert_eq!(None, cache.insert("a", 0));
+    assert_eq!(None, cache.insert("b", 1));
+    assert_eq!(Some(&0), cache.get(&"a"));
+
+    // "b" is evicted because "a" is used recently
+    assert_eq!(Some(("b", 1)), cache.insert("c", 2));
+    assert_eq!(Some(("a", 0)), cache.insert("d", 3));
+    assert_eq!(None, cache.get(&"b"));
+    assert_eq!(2, cache.len());
+}
+
+#[test]
+fn fifo_cache() {
+    let mut cache = FifoCache::new(Some(2));
+    assert_eq!(None, cache.insert("a", 0));
+    assert_eq!(None, cache.insert("b", 1));
+
+    // The removed key is skipped at eviction, and "a" inserted again is newer than "b"
+    assert_eq!(Some(0), cache.remove(&"a"));
+    assert_eq!(None, cache.insert("a", 2));
+    assert_eq!(Some(("b", 1)), cache.insert("c", 3));
+    assert_eq!(Some(("a", 2)), cache.insert("d", 4));
+    assert_eq!(2, cache.len());
+}
+
+#[test]
+fn packed_key() {
+    const ID: u64 = rule_id("p1");
+    let key = PackratKey::new(ID, 0x1234, ());
+    assert_eq!(ID, key.id());
+    assert_eq!(0x1234, key.position());
+    assert_ne!(ID, rule_id("p2"));
+}
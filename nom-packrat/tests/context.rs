@@ -0,0 +1,67 @@
+use nom::branch::alt;
+use nom::character::complete::char;
+use nom::sequence::pair;
+use nom::IResult;
+use nom_locate::LocatedSpan;
+use nom_packrat::{packrat_parser, PackratStorage};
+use std::cell::RefCell;
+
+type Storage = PackratStorage<String, ()>;
+type Span<'a> = LocatedSpan<&'a str, &'a RefCell<Storage>>;
+
+#[packrat_parser(context)]
+pub fn p1(s: Span) -> IResult<Span, String> {
+    alt((p2, p3))(s)
+}
+
+#[packrat_parser(context)]
+pub fn p2(s: Span) -> IResult<Span, String> {
+    let (s, (x, y)) = pair(p4, char('b'))(s)?;
+    Ok((s, format!("{}{}", x, y)))
+}
+
+#[packrat_parser(context)]
+pub fn p3(s: Span) -> IResult<Span, String> {
+    let (s, (x, y)) = pair(p4, char('c'))(s)?;
+    Ok((s, format!("{}{}", x, y)))
+}
+
+#[packrat_parser(context)]
+pub fn p4(s: Span) -> IResult<Span, String> {
+    let (s, x) = char('a')(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[packrat_parser(context, left_recursive)]
+pub fn l1(s: Span) -> IResult<Span, String> {
+    alt((l2, p4))(s)
+}
+
+#[packrat_parser(context)]
+pub fn l2(s: Span) -> IResult<Span, String> {
+    let (s, (x, y)) = pair(l1, char('a'))(s)?;
+    Ok((s, format!("({}{})", x, y)))
+}
+
+#[test]
+fn interleaved() {
+    let storage1 = RefCell::new(Storage::new(None));
+    let storage2 = RefCell::new(Storage::new(None));
+
+    // Both inputs have the same offsets, but the results are kept separately
+    assert!(p2(LocatedSpan::new_extra("ac", &storage1)).is_err());
+    assert_eq!("ab", p2(LocatedSpan::new_extra("ab", &storage2)).unwrap().1);
+    assert_eq!("ac", p1(LocatedSpan::new_extra("ac", &storage1)).unwrap().1);
+    assert_eq!("ab", p1(LocatedSpan::new_extra("ab", &storage2)).unwrap().1);
+
+    assert_eq!(4, storage1.borrow().len());
+    assert_eq!(3, storage2.borrow().len());
+}
+
+#[test]
+fn left_recursive() {
+    let storage = RefCell::new(Storage::new(None));
+    let (s, x) = l1(LocatedSpan::new_extra("aaa", &storage)).unwrap();
+    assert_eq!("", *s.fragment());
+    assert_eq!("((aa)a)", x);
+}
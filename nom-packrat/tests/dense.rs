@@ -0,0 +1,56 @@
+use nom::branch::*;
+use nom::character::complete::*;
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, rule_id, storage, DenseCache, PackratCache, PackratKey};
+
+storage!(cache = nom_packrat::DenseCache, String);
+
+#[packrat_parser]
+pub fn p1(s: &str) -> IResult<&str, String> {
+    alt((p2, p3))(s)
+}
+
+#[packrat_parser]
+pub fn p2(s: &str) -> IResult<&str, String> {
+    let (s, x) = p3(s)?;
+    let (s, _) = char('+')(s)?;
+    let (s, y) = p1(s)?;
+    Ok((s, format!("({}+{})", x, y)))
+}
+
+#[packrat_parser]
+pub fn p3(s: &str) -> IResult<&str, String> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn dense_cache() {
+    init!();
+    let input = "1+2+3";
+    assert_eq!(Ok(("", String::from("(1+(2+3))"))), p1(input));
+
+    // p1, p2 and p3 at offset 0, 2 and 4
+    PACKRAT_STORAGE.with(|storage| assert_eq!(9, storage.borrow().len()));
+
+    // Parse from the middle of the input by the stored results
+    assert_eq!(Ok(("", String::from("(2+3)"))), p1(&input[2..]));
+    PACKRAT_STORAGE.with(|storage| assert_eq!(9, storage.borrow().len()));
+}
+
+#[test]
+fn out_of_input() {
+    let mut cache = DenseCache::new(None);
+    cache.input_range(100..110);
+
+    // A distant position doesn't extend the tables
+    let key = PackratKey::new(rule_id("dense::p3"), 1 << 40, ());
+    assert_eq!(Some((key.clone(), 0)), cache.insert(key.clone(), 0));
+    assert_eq!(None, cache.get(&key));
+    assert_eq!(0, cache.len());
+
+    let key = PackratKey::new(rule_id("dense::p3"), 110, ());
+    assert_eq!(None, cache.insert(key.clone(), 1));
+    assert_eq!(Some(&1), cache.get(&key));
+    assert_eq!(1, cache.len());
+}
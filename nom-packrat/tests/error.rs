@@ -0,0 +1,51 @@
+use nom::character::complete::*;
+use nom::combinator::cut;
+use nom::error::{Error, ErrorKind};
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, storage};
+
+storage!(error = nom_packrat::OffsetError, String);
+
+#[packrat_parser]
+pub fn p1(s: &str) -> IResult<&str, String> {
+    let (s, x) = char('a')(s)?;
+    let (s, y) = char('b')(s)?;
+    Ok((s, format!("{}{}", x, y)))
+}
+
+#[packrat_parser]
+pub fn p2(s: &str) -> IResult<&str, String> {
+    let (s, x) = char('a')(s)?;
+    let (s, y) = cut(char('b'))(s)?;
+    Ok((s, format!("{}{}", x, y)))
+}
+
+#[test]
+fn offset_error() {
+    init!();
+    let input = "ac";
+    let ret = p1(input);
+    assert_eq!(Err(nom::Err::Error(Error::new("c", ErrorKind::Char))), ret);
+
+    // The error is restored from the storage
+    let ret = p1(input);
+    assert_eq!(Err(nom::Err::Error(Error::new("c", ErrorKind::Char))), ret);
+}
+
+#[test]
+fn failure() {
+    init!();
+    let input = "ac";
+    let ret = p2(input);
+    assert_eq!(
+        Err(nom::Err::Failure(Error::new("c", ErrorKind::Char))),
+        ret
+    );
+
+    // `cut` is kept at cache hit
+    let ret = p2(input);
+    assert_eq!(
+        Err(nom::Err::Failure(Error::new("c", ErrorKind::Char))),
+        ret
+    );
+}
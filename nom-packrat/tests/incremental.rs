@@ -0,0 +1,78 @@
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1};
+use nom::IResult;
+use nom_locate::LocatedSpan;
+use nom_packrat::{init, packrat_parser, rule_id, storage, PackratEntry, PackratKey};
+
+storage!(String);
+
+type Span<'a> = LocatedSpan<&'a str>;
+
+#[packrat_parser]
+pub fn expr(s: Span) -> IResult<Span, String> {
+    alt((add, num))(s)
+}
+
+#[packrat_parser]
+pub fn add(s: Span) -> IResult<Span, String> {
+    let (s, x) = num(s)?;
+    let (s, _) = char('+')(s)?;
+    let (s, y) = expr(s)?;
+    Ok((s, format!("({}+{})", x, y)))
+}
+
+#[packrat_parser]
+pub fn num(s: Span) -> IResult<Span, String> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.to_string()))
+}
+
+fn stored_num(position: usize) -> Option<String> {
+    let key = PackratKey::new(rule_id("incremental::num"), position, ());
+    PACKRAT_STORAGE.with(|storage| match storage.borrow_mut().get(&key) {
+        Some(PackratEntry::Accepted(x, _)) => Some(x.clone()),
+        _ => None,
+    })
+}
+
+#[test]
+fn apply_edit() {
+    init!();
+    let (_, x) = expr(Span::new("12+34+56")).unwrap();
+    assert_eq!("(12+(34+56))", x);
+
+    // "34" is replaced with "789"
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().apply_edit(3..5, 3));
+    assert_eq!(None, stored_num(0));
+    assert_eq!(None, stored_num(3));
+    assert_eq!(Some(String::from("56")), stored_num(7));
+
+    let (_, x) = expr(Span::new("12+789+56")).unwrap();
+    assert_eq!("(12+(789+56))", x);
+
+    // "+" is inserted at the end of "12"
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().apply_edit(2..2, 1));
+    assert_eq!(None, stored_num(0));
+    assert_eq!(Some(String::from("789")), stored_num(4));
+
+    assert!(expr(Span::new("12++789+56")).is_ok());
+}
+
+#[packrat_parser]
+pub fn kw(s: Span) -> IResult<Span, String> {
+    let (s, x) = alt((tag("abc"), tag("a")))(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn apply_edit_lookahead() {
+    init!();
+    let (_, x) = kw(Span::new("abd")).unwrap();
+    assert_eq!("a", x);
+
+    // `kw` examined "bd" by `tag("abc")` though it consumed only "a"
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().apply_edit(2..3, 1));
+    let (_, x) = kw(Span::new("abc")).unwrap();
+    assert_eq!("abc", x);
+}
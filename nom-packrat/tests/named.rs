@@ -0,0 +1,58 @@
+use nom::character::complete::{alpha1, digit1};
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, storage};
+
+storage!(name = NUM_STORAGE, i64);
+storage!(name = WORD_STORAGE, String, 16);
+
+#[packrat_parser(storage = NUM_STORAGE)]
+pub fn num(s: &str) -> IResult<&str, i64> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.parse::<i64>().unwrap()))
+}
+
+#[packrat_parser(storage = WORD_STORAGE)]
+pub fn word(s: &str) -> IResult<&str, String> {
+    let (s, x) = alpha1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn named_storage() {
+    init!(NUM_STORAGE);
+    init!(WORD_STORAGE);
+
+    let input = "12ab";
+    assert_eq!(Ok(("ab", 12)), num(input));
+    assert_eq!(Ok(("", String::from("ab"))), word(&input[2..]));
+    assert_eq!(Ok(("ab", 12)), num(input));
+
+    assert_eq!(1, NUM_STORAGE.with(|storage| storage.borrow().len()));
+    assert_eq!(1, WORD_STORAGE.with(|storage| storage.borrow().len()));
+
+    init!(NUM_STORAGE);
+    assert_eq!(0, NUM_STORAGE.with(|storage| storage.borrow().len()));
+    assert_eq!(1, WORD_STORAGE.with(|storage| storage.borrow().len()));
+}
+
+pub mod grammar {
+    use nom_packrat::storage;
+
+    storage!(pub name = STORAGE, String);
+}
+
+#[packrat_parser(storage = grammar::STORAGE)]
+pub fn path_word(s: &str) -> IResult<&str, String> {
+    let (s, x) = alpha1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn storage_path() {
+    init!(grammar::STORAGE);
+    assert_eq!(Ok(("", String::from("ab"))), path_word("ab"));
+    assert_eq!(1, grammar::STORAGE.with(|storage| storage.borrow().len()));
+
+    init!(crate::grammar::STORAGE);
+    assert_eq!(0, grammar::STORAGE.with(|storage| storage.borrow().len()));
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "serde")]
+
+use nom::branch::alt;
+use nom::character::complete::{char, digit1};
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, storage};
+
+storage!(String);
+
+#[packrat_parser]
+pub fn expr(s: &str) -> IResult<&str, String> {
+    alt((add, num))(s)
+}
+
+#[packrat_parser]
+pub fn add(s: &str) -> IResult<&str, String> {
+    let (s, x) = num(s)?;
+    let (s, _) = char('+')(s)?;
+    let (s, y) = expr(s)?;
+    Ok((s, format!("({}+{})", x, y)))
+}
+
+#[packrat_parser]
+pub fn num(s: &str) -> IResult<&str, String> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn save_load() {
+    let input = String::from("1+2+3");
+    init!();
+    expr(&input).unwrap();
+    let dump = PACKRAT_STORAGE.with(|s| s.borrow().save(&input.as_str()));
+    let len = PACKRAT_STORAGE.with(|s| s.borrow().len());
+    assert_eq!(len, dump.len());
+    let json = serde_json::to_string(&dump).unwrap();
+    drop(input);
+
+    // The same content is loaded at another address
+    let input = format!("{}+{}+{}", 1, 2, 3);
+    init!();
+    let dump = serde_json::from_str(&json).unwrap();
+    assert!(PACKRAT_STORAGE.with(|s| s.borrow_mut().load(&input.as_str(), dump)));
+    assert_eq!(len, PACKRAT_STORAGE.with(|s| s.borrow().len()));
+
+    // Cache hit at the head returns the saved result without parsing
+    assert_eq!(Ok(("", String::from("(1+(2+3))"))), expr(&input));
+    assert_eq!(len, PACKRAT_STORAGE.with(|s| s.borrow().len()));
+
+    // Changed content is rejected
+    let input = String::from("1+2+4");
+    init!();
+    let dump = serde_json::from_str(&json).unwrap();
+    assert!(!PACKRAT_STORAGE.with(|s| s.borrow_mut().load(&input.as_str(), dump)));
+    assert!(PACKRAT_STORAGE.with(|s| s.borrow().is_empty()));
+}
+
+mod fifo {
+    use super::*;
+
+    storage!(name = FIFO_STORAGE, String, 2);
+
+    #[packrat_parser(storage = FIFO_STORAGE)]
+    pub fn num(s: &str) -> IResult<&str, String> {
+        let (s, x) = digit1(s)?;
+        Ok((s, x.to_string()))
+    }
+
+    fn offsets(input: &str) -> Vec<u64> {
+        let dump = FIFO_STORAGE.with(|s| s.borrow().save(&input));
+        let json = serde_json::to_value(&dump).unwrap();
+        let mut ret: Vec<_> = json["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x[1].as_u64().unwrap())
+            .collect();
+        ret.sort_unstable();
+        ret
+    }
+
+    #[test]
+    fn save_keeps_order() {
+        let input = String::from("1+2+3");
+        init!(FIFO_STORAGE);
+        num(&input).unwrap();
+        num(&input[2..]).unwrap();
+        assert_eq!(vec![0, 2], offsets(&input));
+
+        // The oldest entry is still evicted first after saving
+        num(&input[4..]).unwrap();
+        assert_eq!(vec![2, 4], offsets(&input));
+    }
+}
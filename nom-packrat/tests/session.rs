@@ -0,0 +1,88 @@
+use nom::character::complete::char;
+use nom::IResult;
+use nom_packrat::{packrat_parser, session, storage, PackratSession};
+
+storage!(String);
+
+#[packrat_parser]
+pub fn p1(s: &str) -> IResult<&str, String> {
+    let (s, x) = char('a')(s)?;
+    Ok((s, x.to_string()))
+}
+
+fn len() -> usize {
+    PACKRAT_STORAGE.with(|storage| storage.borrow().len())
+}
+
+#[test]
+fn session_clear() {
+    let input = String::from("a");
+    let ret = session(&PACKRAT_STORAGE, || {
+        let ret = p1(&input);
+        assert_eq!(1, len());
+        ret
+    });
+    assert_eq!(Ok(("", String::from("a"))), ret);
+    assert_eq!(0, len());
+}
+
+#[test]
+fn nested_session() {
+    let input = String::from("a");
+    let outer = PackratSession::new(&PACKRAT_STORAGE);
+    assert!(p1(&input).is_ok());
+    {
+        let _inner = PackratSession::new(&PACKRAT_STORAGE);
+        assert_eq!(1, len());
+    }
+    assert_eq!(1, len());
+    drop(outer);
+    assert_eq!(0, len());
+}
+
+#[test]
+fn stale_input() {
+    let input1 = String::from("a");
+    let input2 = String::from("b");
+
+    session(&PACKRAT_STORAGE, || {
+        assert!(p1(&input1).is_ok());
+        assert_eq!(1, len());
+
+        // `input2` is out of the range of `input1`, so the result of `input1` is dropped
+        assert!(p1(&input2).is_err());
+        assert_eq!(1, len());
+
+        // The suffix of `input2` is in the range
+        assert!(p1(&input2[1..]).is_err());
+        assert_eq!(2, len());
+    });
+}
+
+#[packrat_parser]
+pub fn p2(s: &str) -> IResult<&str, String> {
+    // Parse another input inside the running parser
+    let other = String::from("a");
+    let (_, x) = p1(&other)
+        .map_err(|_| nom::Err::Error(nom::error::Error::new(s, nom::error::ErrorKind::Char)))?;
+    let (s, y) = p1(s)?;
+    Ok((s, x + &y))
+}
+
+#[test]
+fn nested_input() {
+    let input = String::from("a");
+
+    session(&PACKRAT_STORAGE, || {
+        assert!(p1(&input).is_ok());
+        assert_eq!(1, len());
+
+        // The result of `input` is kept while `p2` is running
+        assert_eq!(Ok(("", String::from("aa"))), p2(&input));
+        assert_eq!(3, len());
+
+        // The range of input is not changed by the inner input
+        assert!(p1(&input).is_ok());
+        assert_eq!(3, len());
+    });
+}
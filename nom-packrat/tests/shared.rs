@@ -0,0 +1,140 @@
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::alpha1;
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, session, storage};
+use std::cell::Cell;
+
+storage!(String);
+
+thread_local!(static CALLS: Cell<usize> = const { Cell::new(0) });
+
+#[packrat_parser(shared)]
+pub fn ty(s: &str) -> IResult<&str, String> {
+    CALLS.with(|x| x.set(x.get() + 1));
+    let (s, x) = alpha1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[packrat_parser]
+pub fn decl(s: &str) -> IResult<&str, String> {
+    let (s, x) = alpha1(s)?;
+    let (s, _) = tag(":")(s)?;
+    let (s, y) = ty(s)?;
+    Ok((s, format!("{}={}", x, y)))
+}
+
+fn calls() -> usize {
+    CALLS.with(|x| x.get())
+}
+
+#[test]
+fn shared() {
+    init!();
+    assert_eq!(
+        Ok(("", String::from("x=int"))),
+        decl(&String::from("x:int"))
+    );
+    assert_eq!(1, calls());
+
+    // The same remaining input in another parse reuses the result
+    init!();
+    assert_eq!(
+        Ok(("", String::from("y=int"))),
+        decl(&String::from("y:int"))
+    );
+    assert_eq!(1, calls());
+    assert_eq!(1, PACKRAT_STORAGE.with(|s| s.borrow().shared_len()));
+
+    // Another remaining input is parsed even if the consumed input is the same
+    init!();
+    assert_eq!(
+        Ok((";", String::from("x=int"))),
+        decl(&String::from("x:int;"))
+    );
+    assert_eq!(2, calls());
+
+    init!();
+    assert!(decl(&String::from("z:")).is_err());
+    init!();
+    assert!(decl(&String::from("z:")).is_err());
+    assert_eq!(3, calls());
+
+    // The shared results are kept by `release`
+    PACKRAT_STORAGE.with(|s| s.borrow_mut().release());
+    assert_eq!(3, PACKRAT_STORAGE.with(|s| s.borrow().shared_len()));
+
+    PACKRAT_STORAGE.with(|s| s.borrow_mut().clear_shared());
+    init!();
+    assert_eq!(
+        Ok(("", String::from("x=int"))),
+        decl(&String::from("x:int"))
+    );
+    assert_eq!(4, calls());
+}
+
+#[packrat_parser(shared)]
+pub fn kw(s: &str) -> IResult<&str, String> {
+    let (s, x) = alt((tag("abc"), tag("a")))(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn shared_lookahead() {
+    init!();
+    assert_eq!(Ok(("bd", String::from("a"))), kw(&String::from("abd")));
+
+    // `kw` examined "bd" by `tag("abc")` though it consumed only "a"
+    init!();
+    assert_eq!(Ok(("", String::from("abc"))), kw(&String::from("abc")));
+}
+
+#[packrat_parser(shared)]
+pub fn word(s: &str) -> IResult<&str, String> {
+    WORD_CALLS.with(|x| x.set(x.get() + 1));
+    let (s, x) = alpha1(s)?;
+    Ok((s, x.to_string()))
+}
+
+thread_local!(static WORD_CALLS: Cell<usize> = const { Cell::new(0) });
+
+#[test]
+fn shared_session() {
+    // The shared results are kept by the end of session
+    for _ in 0..3 {
+        let input = String::from("int");
+        let ret = session(&PACKRAT_STORAGE, || word(&input));
+        assert_eq!(Ok(("", String::from("int"))), ret);
+    }
+    assert_eq!(1, WORD_CALLS.with(|x| x.get()));
+
+    // The long remaining input is not shared
+    let input = "a".repeat(2048);
+    for _ in 0..2 {
+        session(&PACKRAT_STORAGE, || word(&input)).unwrap();
+    }
+    assert_eq!(3, WORD_CALLS.with(|x| x.get()));
+}
+
+mod small {
+    use super::*;
+
+    storage!(name = SMALL_STORAGE, String, 2);
+
+    #[packrat_parser(shared, storage = SMALL_STORAGE)]
+    pub fn ty(s: &str) -> IResult<&str, String> {
+        let (s, x) = alpha1(s)?;
+        Ok((s, x.to_string()))
+    }
+
+    #[test]
+    fn shared_size() {
+        for input in &["int", "long", "char"] {
+            init!(SMALL_STORAGE);
+            assert!(ty(&String::from(*input)).is_ok());
+        }
+
+        // The shared results are limited by the storage size
+        assert_eq!(2, SMALL_STORAGE.with(|s| s.borrow().shared_len()));
+    }
+}
@@ -0,0 +1,134 @@
+use nom::branch::alt;
+use nom::character::complete::{char, digit1};
+use nom::IResult;
+use nom_locate::LocatedSpan;
+use nom_packrat::{
+    init, packrat_parser, rule_id, storage, HasExtraState, PackratEntry, PackratKey,
+};
+
+storage!(String, bool);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Mode {
+    speculative: bool,
+}
+
+impl HasExtraState<bool> for Mode {
+    fn get_extra_state(&self) -> bool {
+        self.speculative
+    }
+}
+
+type Span<'a> = LocatedSpan<&'a str, Mode>;
+
+fn span(s: &str, speculative: bool) -> Span<'_> {
+    Span::new_extra(s, Mode { speculative })
+}
+
+#[packrat_parser]
+pub fn num(s: Span) -> IResult<Span, String> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[packrat_parser]
+pub fn pair(s: Span) -> IResult<Span, String> {
+    let (s, x) = num(s)?;
+    let (s, _) = char(',')(s)?;
+    let (s, y) = num(s)?;
+    Ok((s, format!("({},{})", x, y)))
+}
+
+pub fn speculative(s: Span) -> IResult<Span, String> {
+    let snapshot = PACKRAT_STORAGE.with(|storage| storage.borrow_mut().snapshot());
+    let spec = span(s.fragment(), true);
+    let ret = pair(spec);
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().rollback(snapshot));
+    match ret {
+        Ok((_, x)) => Ok((s, x)),
+        Err(e) => Err(e.map(|e| nom::error::Error::new(s, e.code))),
+    }
+}
+
+fn stored(name: &str, position: usize, extra: bool) -> bool {
+    let key = PackratKey::new(rule_id(name), position, extra);
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().get(&key).is_some())
+}
+
+fn len() -> usize {
+    PACKRAT_STORAGE.with(|storage| storage.borrow().len())
+}
+
+#[test]
+fn rollback() {
+    init!();
+    let input = span("1,2", false);
+    num(input).unwrap();
+    assert_eq!(1, len());
+
+    // Entries stored in the speculative branch are discarded
+    let (_, x) = alt((speculative, num))(input).unwrap();
+    assert_eq!("(1,2)", x);
+    assert_eq!(1, len());
+    assert!(stored("snapshot::num", 0, false));
+    assert!(!stored("snapshot::num", 0, true));
+    assert!(!stored("snapshot::pair", 0, true));
+
+    // Nested snapshot is committed into the outer one
+    let outer = PACKRAT_STORAGE.with(|storage| storage.borrow_mut().snapshot());
+    let inner = PACKRAT_STORAGE.with(|storage| storage.borrow_mut().snapshot());
+    pair(input).unwrap();
+    assert_eq!(3, len());
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().commit(inner));
+    assert_eq!(3, len());
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().rollback(outer));
+    assert_eq!(1, len());
+    assert!(stored("snapshot::num", 0, false));
+}
+
+#[test]
+fn rollback_replaced() {
+    init!();
+    let input = span("1", false);
+    let key = PackratKey::new(rule_id("snapshot::num"), 0, false);
+    num(input).unwrap();
+
+    // The replaced entry is restored
+    let snapshot = PACKRAT_STORAGE.with(|storage| storage.borrow_mut().snapshot());
+    PACKRAT_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        storage.insert(key.clone(), PackratEntry::Accepted(String::from("2"), 1));
+        storage.rollback(snapshot);
+        assert!(matches!(storage.get(&key), Some(PackratEntry::Accepted(x, 1)) if x == "1"));
+        assert_eq!(1, storage.len());
+    });
+
+    // The removed entry is restored
+    let snapshot = PACKRAT_STORAGE.with(|storage| storage.borrow_mut().snapshot());
+    PACKRAT_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        storage.remove(&key);
+        assert!(storage.is_empty());
+        storage.rollback(snapshot);
+        assert_eq!(1, storage.len());
+    });
+}
+
+#[test]
+fn rollback_edited() {
+    init!();
+    let input = span("1,2", false);
+    let outer = PACKRAT_STORAGE.with(|storage| storage.borrow_mut().snapshot());
+    num(input).unwrap();
+    let inner = PACKRAT_STORAGE.with(|storage| storage.borrow_mut().snapshot());
+    pair(input).unwrap();
+    assert_eq!(3, len());
+
+    // The entries kept by the edit can't be told apart, so all of them are discarded
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().apply_edit(0..0, 1));
+    assert_eq!(1, len());
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().rollback(inner));
+    assert_eq!(0, len());
+    PACKRAT_STORAGE.with(|storage| storage.borrow_mut().rollback(outer));
+    assert_eq!(0, len());
+}
@@ -0,0 +1,123 @@
+#![cfg(feature = "stats")]
+
+use nom::branch::alt;
+use nom::character::complete::{alpha1, char, digit1};
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, rule_id, storage, PackratEntry, PackratInput, PackratKey};
+
+storage!(String);
+storage!(name = SMALL_STORAGE, String, 1);
+
+#[packrat_parser]
+pub fn expr(s: &str) -> IResult<&str, String> {
+    alt((add, num))(s)
+}
+
+#[packrat_parser]
+pub fn add(s: &str) -> IResult<&str, String> {
+    let (s, x) = num(s)?;
+    let (s, _) = char('+')(s)?;
+    let (s, y) = expr(s)?;
+    Ok((s, format!("({}+{})", x, y)))
+}
+
+#[packrat_parser]
+pub fn num(s: &str) -> IResult<&str, String> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[packrat_parser(storage = SMALL_STORAGE)]
+pub fn digit(s: &str) -> IResult<&str, String> {
+    let (s, x) = digit1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn stats() {
+    init!();
+    expr("1+2").unwrap();
+    expr("x").unwrap_err();
+    let stats = PACKRAT_STORAGE.with(|s| s.borrow().stats());
+    let names: Vec<_> = stats.rules.iter().map(|x| x.name).collect();
+    assert_eq!(vec!["stats::add", "stats::expr", "stats::num"], names);
+
+    // `num` is hit after `add` fails at "2" and "x"
+    let num = &stats.rules[2];
+    assert_eq!(
+        (1, 1, 3, 3, 0),
+        (
+            num.accepted_hits,
+            num.rejected_hits,
+            num.misses,
+            num.stores,
+            num.evictions
+        )
+    );
+
+    let table = stats.to_string();
+    assert!(table.starts_with("rule"));
+    assert!(table.contains("stats::num"));
+
+    PACKRAT_STORAGE.with(|s| s.borrow_mut().clear_stats());
+    assert!(PACKRAT_STORAGE.with(|s| s.borrow().stats().rules.is_empty()));
+}
+
+#[test]
+fn evictions() {
+    init!(SMALL_STORAGE);
+    let input = "12";
+    digit(input).unwrap();
+    digit(&input[1..]).unwrap();
+    digit(input).unwrap();
+
+    let stats = SMALL_STORAGE.with(|s| s.borrow().stats());
+    let digit = &stats.rules[0];
+    assert_eq!(
+        (0, 3, 3, 2),
+        (
+            digit.accepted_hits,
+            digit.misses,
+            digit.stores,
+            digit.evictions
+        )
+    );
+}
+
+#[test]
+fn direct_insert() {
+    init!(SMALL_STORAGE);
+    let input = "1";
+    let key = PackratKey::new(rule_id("stats::other"), input.packrat_position(), ());
+    SMALL_STORAGE.with(|s| {
+        s.borrow_mut()
+            .insert(key, PackratEntry::Accepted(String::from("1"), 1))
+    });
+
+    // The direct insert is not counted, and its eviction doesn't add a row without the name
+    digit(input).unwrap();
+    let stats = SMALL_STORAGE.with(|s| s.borrow().stats());
+    let names: Vec<_> = stats.rules.iter().map(|x| x.name).collect();
+    assert_eq!(vec!["stats::digit"], names);
+    assert_eq!((1, 0), (stats.rules[0].stores, stats.rules[0].evictions));
+}
+
+#[packrat_parser(shared)]
+pub fn word(s: &str) -> IResult<&str, String> {
+    let (s, x) = alpha1(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn shared_hit() {
+    init!();
+    word(&String::from("int")).unwrap();
+    init!();
+    word(&String::from("int")).unwrap();
+
+    // The result copied from the shared results is a hit
+    let stats = PACKRAT_STORAGE.with(|s| s.borrow().stats());
+    let word = &stats.rules[0];
+    assert_eq!("stats::word", word.name);
+    assert_eq!((1, 1, 1), (word.accepted_hits, word.misses, word.stores));
+}
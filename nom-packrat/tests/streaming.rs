@@ -0,0 +1,49 @@
+use nom::bytes::streaming::tag;
+use nom::IResult;
+use nom_locate::LocatedSpan;
+use nom_packrat::{init, packrat_parser, storage};
+
+storage!(String);
+
+type Span<'a> = LocatedSpan<&'a str>;
+
+#[packrat_parser]
+pub fn p1(s: &str) -> IResult<&str, String> {
+    let (s, x) = tag("abc")(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn incomplete() {
+    init!();
+    let mut buf = String::with_capacity(16);
+    let ptr = buf.as_ptr();
+
+    buf.push_str("ab");
+    assert_eq!(Err(nom::Err::Incomplete(nom::Needed::new(1))), p1(&buf));
+
+    buf.push('c');
+    assert_eq!(ptr, buf.as_ptr());
+    assert_eq!(Ok(("", String::from("abc"))), p1(&buf));
+}
+
+#[packrat_parser]
+pub fn p2(s: Span) -> IResult<Span, String> {
+    let (s, x) = tag("abc")(s)?;
+    Ok((s, x.to_string()))
+}
+
+#[test]
+fn located_span_reallocated() {
+    init!();
+    let mut buf = String::with_capacity(2);
+
+    buf.push_str("ab");
+    assert_eq!(
+        Err(nom::Err::Incomplete(nom::Needed::new(1))),
+        p2(Span::new(&buf)).map(|(_, x)| x)
+    );
+
+    buf.push_str(&"c".repeat(1024));
+    assert_eq!(Ok(String::from("abc")), p2(Span::new(&buf)).map(|(_, x)| x));
+}
@@ -66,3 +66,194 @@ fn it_works() {
         format!("{:?}", ret)
     );
 }
+
+#[packrat_parser(left_recursive)]
+pub fn l1(s: &str) -> IResult<&str, Vec<String>> {
+    alt((l2, l3))(s)
+}
+
+#[packrat_parser]
+pub fn l2(s: &str) -> IResult<&str, Vec<String>> {
+    let (s, x) = l1(s)?;
+    let (s, _) = char('-')(s)?;
+    let (s, y) = l3(s)?;
+    Ok((s, vec![format!("({}-{})", x[0], y[0])]))
+}
+
+#[packrat_parser]
+pub fn l3(s: &str) -> IResult<&str, Vec<String>> {
+    let (s, x) = digit1(s)?;
+    Ok((s, vec![x.to_string()]))
+}
+
+#[test]
+fn left_recursive() {
+    init!();
+    let ret = l1("1-2-3");
+    assert_eq!("Ok((\"\", [\"((1-2)-3)\"]))", format!("{:?}", ret));
+}
+
+#[test]
+fn left_recursive_inner() {
+    // The inner rule called first is rejected at the re-entry while the seed of `l1` grows
+    init!();
+    let ret = l2("1-2-3");
+    assert_eq!("Ok((\"-3\", [\"(1-2)\"]))", format!("{:?}", ret));
+
+    // The results depending on the rejection are not stored
+    let ret = l1("1-2-3");
+    assert_eq!("Ok((\"\", [\"((1-2)-3)\"]))", format!("{:?}", ret));
+}
+
+// The seed is not evicted even if the storage keeps only one entry
+mod tiny {
+    use super::*;
+
+    storage!(name = TINY_STORAGE, Vec<String>, 1);
+
+    #[packrat_parser(left_recursive, storage = TINY_STORAGE)]
+    pub fn l1(s: &str) -> IResult<&str, Vec<String>> {
+        alt((l2, l3))(s)
+    }
+
+    #[packrat_parser(storage = TINY_STORAGE)]
+    pub fn l2(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, x) = l1(s)?;
+        let (s, _) = char('-')(s)?;
+        let (s, y) = l3(s)?;
+        Ok((s, vec![format!("({}-{})", x[0], y[0])]))
+    }
+}
+
+#[test]
+fn left_recursive_small_storage() {
+    init!(tiny::TINY_STORAGE);
+    let ret = tiny::l1("1-2-3");
+    assert_eq!("Ok((\"\", [\"((1-2)-3)\"]))", format!("{:?}", ret));
+}
+
+#[packrat_parser]
+pub fn r1(s: &str) -> IResult<&str, Vec<String>> {
+    alt((r2, l3))(s)
+}
+
+#[packrat_parser]
+pub fn r2(s: &str) -> IResult<&str, Vec<String>> {
+    let (s, x) = r1(s)?;
+    let (s, _) = char('-')(s)?;
+    Ok((s, x))
+}
+
+#[test]
+#[should_panic(expected = "left recursion detected: `test::r1` is called again at offset 0")]
+fn left_recursion_detected() {
+    init!();
+    let _ = r1("1-2");
+}
+
+// The marks of running parsers are not evicted even if the cycle is longer than the storage size
+mod cycle {
+    use super::*;
+
+    storage!(name = CYCLE_STORAGE, Vec<String>, 4);
+
+    #[packrat_parser(storage = CYCLE_STORAGE)]
+    pub fn c1(s: &str) -> IResult<&str, Vec<String>> {
+        alt((c2, l3))(s)
+    }
+
+    #[packrat_parser(storage = CYCLE_STORAGE)]
+    pub fn c2(s: &str) -> IResult<&str, Vec<String>> {
+        c3(s)
+    }
+
+    #[packrat_parser(storage = CYCLE_STORAGE)]
+    pub fn c3(s: &str) -> IResult<&str, Vec<String>> {
+        c4(s)
+    }
+
+    #[packrat_parser(storage = CYCLE_STORAGE)]
+    pub fn c4(s: &str) -> IResult<&str, Vec<String>> {
+        c5(s)
+    }
+
+    #[packrat_parser(storage = CYCLE_STORAGE)]
+    pub fn c5(s: &str) -> IResult<&str, Vec<String>> {
+        c6(s)
+    }
+
+    #[packrat_parser(storage = CYCLE_STORAGE)]
+    pub fn c6(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, x) = c1(s)?;
+        let (s, _) = char('-')(s)?;
+        Ok((s, x))
+    }
+}
+
+#[test]
+#[should_panic(expected = "left recursion detected: `test::cycle::c1` is called again at offset 0")]
+fn left_recursion_detected_small_storage() {
+    init!(cycle::CYCLE_STORAGE);
+    let _ = cycle::c1("1-2");
+}
+
+mod m1 {
+    use super::*;
+
+    #[packrat_parser]
+    pub fn term(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, x) = digit1(s)?;
+        Ok((s, vec![String::from("m1"), x.to_string()]))
+    }
+}
+
+mod m2 {
+    use super::*;
+
+    #[packrat_parser]
+    pub fn term(s: &str) -> IResult<&str, Vec<String>> {
+        let (s, x) = digit1(s)?;
+        Ok((s, vec![String::from("m2"), x.to_string()]))
+    }
+}
+
+#[test]
+fn same_name() {
+    init!();
+    let input = "1";
+    assert_eq!(
+        "Ok((\"\", [\"m1\", \"1\"]))",
+        format!("{:?}", m1::term(input))
+    );
+    assert_eq!(
+        "Ok((\"\", [\"m2\", \"1\"]))",
+        format!("{:?}", m2::term(input))
+    );
+}
+
+#[test]
+fn storage_is_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<nom_packrat::PackratStorage<Vec<String>, ()>>();
+}
+
+#[test]
+fn lazy_clear() {
+    let mut buf = String::from("a+a");
+    init!();
+    assert!(p1(&buf).is_ok());
+    let stored = PACKRAT_STORAGE.with(|storage| storage.borrow().len());
+
+    // The entries are kept in the cache, but they are not used after clear
+    init!();
+    PACKRAT_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        assert_eq!(0, storage.len());
+        assert_eq!(stored, nom_packrat::PackratCache::len(storage.cache()));
+    });
+
+    // The new input at the same address
+    buf.replace_range(.., "(a)");
+    let ret = p1(&buf);
+    assert_eq!("Ok((\"\", [\"(\", \"a\", \")\"]))", format!("{:?}", ret));
+}
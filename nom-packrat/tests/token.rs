@@ -0,0 +1,71 @@
+use nom::branch::alt;
+use nom::error::{make_error, ErrorKind};
+use nom::sequence::tuple;
+use nom::IResult;
+use nom_packrat::{init, packrat_parser, storage};
+
+storage!(i64);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    Num(i64),
+    Plus,
+    Minus,
+}
+
+fn token(expected: Token) -> impl Fn(&[Token]) -> IResult<&[Token], Token> {
+    move |s: &[Token]| match s.first() {
+        Some(x) if *x == expected => Ok((&s[1..], *x)),
+        _ => Err(nom::Err::Error(make_error(s, ErrorKind::Tag))),
+    }
+}
+
+#[packrat_parser]
+pub fn num(s: &[Token]) -> IResult<&[Token], i64> {
+    match s.first() {
+        Some(Token::Num(x)) => Ok((&s[1..], *x)),
+        _ => Err(nom::Err::Error(make_error(s, ErrorKind::Digit))),
+    }
+}
+
+#[packrat_parser(left_recursive)]
+pub fn expr(s: &[Token]) -> IResult<&[Token], i64> {
+    alt((add, sub, num))(s)
+}
+
+#[packrat_parser]
+pub fn add(s: &[Token]) -> IResult<&[Token], i64> {
+    let (s, (x, _, y)) = tuple((expr, token(Token::Plus), num))(s)?;
+    Ok((s, x + y))
+}
+
+#[packrat_parser]
+pub fn sub(s: &[Token]) -> IResult<&[Token], i64> {
+    let (s, (x, _, y)) = tuple((expr, token(Token::Minus), num))(s)?;
+    Ok((s, x - y))
+}
+
+#[test]
+fn token_slice() {
+    let tokens = vec![
+        Token::Num(1),
+        Token::Minus,
+        Token::Num(2),
+        Token::Plus,
+        Token::Num(3),
+    ];
+
+    init!();
+    let (s, x) = expr(&tokens).unwrap();
+    assert!(s.is_empty());
+    assert_eq!(2, x);
+
+    // Cache hit skips the consumed tokens
+    let (s, x) = num(&tokens[4..]).unwrap();
+    assert!(s.is_empty());
+    assert_eq!(3, x);
+
+    let (s, x) = expr(&tokens[2..]).unwrap();
+    assert!(s.is_empty());
+    assert_eq!(5, x);
+}